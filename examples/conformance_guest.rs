@@ -0,0 +1,35 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reference guest of conformance checks, built into WASM module with
+//!
+//! ```sh
+//! cargo build --release --example conformance_guest \
+//!   --target wasm32-unknown-unknown \
+//!   --no-default-features --features std,raw-abi,conformance
+//! ```
+//!
+//! Adding features `get-buffer`, `try-send` and `send-parts` makes guest with
+//! the other handshake and imports of message passing, version 1.
+//!
+
+use wasm_message_passing_3nweb::conformance::guest;
+
+/// Starts reference guest. Embedder calls this after instantiation.
+///
+#[no_mangle]
+pub extern "C" fn _3nweb_conformance_start() {
+	guest::install();
+}
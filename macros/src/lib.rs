@@ -0,0 +1,213 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Procedural macros of `wasm-message-passing-3nweb` crate. They are used
+//! through re-exports in its `service` module.
+//!
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+	parse_macro_input, Error, FnArg, Ident, ItemTrait, Pat, ReturnType,
+	TraitItem, TraitItemFn, Type
+};
+
+/// Method of service trait, as it is needed for generated code.
+///
+struct ServiceMethod<'a> {
+	name: &'a Ident,
+	docs: Vec<&'a syn::Attribute>,
+	args: Vec<(&'a Ident, &'a Type)>,
+	ret: TokenStream2,
+}
+
+impl<'a> ServiceMethod<'a> {
+
+	fn from_trait_fn(method: &'a TraitItemFn) -> Result<Self, Error> {
+		let sig = &method.sig;
+		if let Some(asyncness) = sig.asyncness {
+			return Err(Error::new_spanned(asyncness, "service methods can't be async"));
+		}
+		if !sig.generics.params.is_empty() {
+			return Err(Error::new_spanned(&sig.generics, "service methods can't be generic"));
+		}
+		let mut inputs = sig.inputs.iter();
+		match inputs.next() {
+			Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() => (),
+			_ => return Err(Error::new_spanned(
+				sig, "service methods should take `&self` or `&mut self`"
+			)),
+		}
+		let args = inputs.map(|arg| match arg {
+			FnArg::Typed(arg) => match arg.pat.as_ref() {
+				Pat::Ident(pat) => Ok((&pat.ident, arg.ty.as_ref())),
+				pat => Err(Error::new_spanned(pat, "argument should be a plain name")),
+			},
+			FnArg::Receiver(receiver) => Err(Error::new_spanned(receiver, "unexpected receiver")),
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+		let ret = match &sig.output {
+			ReturnType::Default => quote!(()),
+			ReturnType::Type(_, ty) => quote!(#ty),
+		};
+		let docs = method.attrs.iter()
+		.filter(|attr| attr.path().is_ident("doc"))
+		.collect();
+		Ok(ServiceMethod { name: &sig.ident, docs, args, ret })
+	}
+
+}
+
+/// Generates dispatcher and client of a service from a trait definition.
+///
+/// For trait `Foo`, it generates:
+/// - `FooDispatcher<S, C>`, which implements `service::Dispatch`, decoding
+///   requests with codec `C`, and calling methods of `S: Foo`,
+/// - `FooClient<T, C>`, which has an async method per trait method, making
+///   calls with transport `T: service::Transport`.
+///
+#[proc_macro_attribute]
+pub fn mp1_service(attr: TokenStream, item: TokenStream) -> TokenStream {
+	if !attr.is_empty() {
+		return Error::new(
+			proc_macro2::Span::call_site(), "mp1_service takes no arguments"
+		)
+		.to_compile_error()
+		.into();
+	}
+	let service = parse_macro_input!(item as ItemTrait);
+	match expand(&service) {
+		Ok(tokens) => tokens.into(),
+		Err(err) => err.to_compile_error().into(),
+	}
+}
+
+fn expand(service: &ItemTrait) -> Result<TokenStream2, Error> {
+	if !service.generics.params.is_empty() {
+		return Err(Error::new_spanned(&service.generics, "service trait can't be generic"));
+	}
+	let methods = service.items.iter()
+	.filter_map(|item| match item {
+		TraitItem::Fn(method) => Some(ServiceMethod::from_trait_fn(method)),
+		_ => None,
+	})
+	.collect::<Result<Vec<_>, _>>()?;
+
+	let vis = &service.vis;
+	let trait_name = &service.ident;
+	let dispatcher = format_ident!("{}Dispatcher", trait_name);
+	let client = format_ident!("{}Client", trait_name);
+	let dispatcher_doc = format!(
+		"Dispatcher of requests to methods of [`{trait_name}`] service, generated by `mp1_service`."
+	);
+	let client_doc = format!(
+		"Client of [`{trait_name}`] service, generated by `mp1_service`."
+	);
+	let srv = quote!(::wasm_message_passing_3nweb::service);
+
+	let dispatch_arms = methods.iter().map(|method| {
+		let name = method.name;
+		let name_str = name.to_string();
+		let arg_names = method.args.iter().map(|(name, _)| name);
+		let arg_names_again = arg_names.clone();
+		let arg_types = method.args.iter().map(|(_, ty)| ty);
+		quote! {
+			#name_str => {
+				let (#(#arg_names,)*): (#(#arg_types,)*) = self.codec.decode(args)
+				.map_err(|err| #srv::ServiceError::BadRequest(err.to_string()))?;
+				let reply = <S as #trait_name>::#name(&mut self.service, #(#arg_names_again),*);
+				self.codec.encode(&reply).map_err(#srv::ServiceError::codec)
+			},
+		}
+	});
+
+	let client_methods = methods.iter().map(|method| {
+		let name = method.name;
+		let name_str = name.to_string();
+		let docs = &method.docs;
+		let ret = &method.ret;
+		let params = method.args.iter().map(|(name, ty)| quote!(#name: #ty));
+		let arg_names = method.args.iter().map(|(name, _)| name);
+		quote! {
+			#(#docs)*
+			pub async fn #name(
+				&mut self, #(#params),*
+			) -> ::std::result::Result<#ret, #srv::ServiceError> {
+				let args = self.codec.encode(&(#(&#arg_names,)*))
+				.map_err(#srv::ServiceError::codec)?;
+				let reply = #srv::Transport::call(
+					&mut self.transport, #srv::encode_request(#name_str, &args)
+				).await?;
+				self.codec.decode(&reply).map_err(#srv::ServiceError::codec)
+			}
+		}
+	});
+
+	Ok(quote! {
+		#service
+
+		#[doc = #dispatcher_doc]
+		#vis struct #dispatcher<S, C> {
+			service: S,
+			codec: C,
+		}
+
+		impl<S, C> #dispatcher<S, C> {
+
+			pub fn new(service: S, codec: C) -> Self {
+				#dispatcher { service, codec }
+			}
+
+			pub fn service(&mut self) -> &mut S {
+				&mut self.service
+			}
+
+		}
+
+		impl<S: #trait_name, C: #srv::Codec> #srv::Dispatch for #dispatcher<S, C> {
+			fn dispatch(
+				&mut self, request: &[u8]
+			) -> ::std::result::Result<::std::vec::Vec<u8>, #srv::ServiceError> {
+				let (method, args) = #srv::decode_request(request)
+				.ok_or_else(|| #srv::ServiceError::BadRequest("malformed request".to_string()))?;
+				match method {
+					#(#dispatch_arms)*
+					_ => Err(#srv::ServiceError::UnknownMethod(method.to_string())),
+				}
+			}
+		}
+
+		#[doc = #client_doc]
+		#vis struct #client<T, C> {
+			transport: T,
+			codec: C,
+		}
+
+		impl<T: #srv::Transport, C: #srv::Codec> #client<T, C> {
+
+			pub fn new(transport: T, codec: C) -> Self {
+				#client { transport, codec }
+			}
+
+			pub fn transport(&mut self) -> &mut T {
+				&mut self.transport
+			}
+
+			#(#client_methods)*
+
+		}
+	})
+}
@@ -0,0 +1,632 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module streams large payloads in chunks on top of message passing,
+//! version 1, so that neither side has to hold the whole payload as one
+//! message.
+//!
+//! Every message is framed with a header of 5 bytes: kind of frame in the
+//! first byte, followed by stream id as little-endian `u32`. The rest of
+//! message is a body. Frame kinds are:
+//! - chunk, which body is the next piece of stream's payload,
+//! - end, which tells that stream is complete,
+//! - abort, which tells that sender gave up stream, with reason in the body,
+//! - cancel, which receiver sends to tell sender to stop. Sender answers it
+//!   with abort, after which stream id is free.
+//!
+//! Each side numbers its own streams, and frames of chunk, end and abort kinds
+//! carry id of sender's stream, while cancel carries id of receiver's one.
+//!
+//! Streams to the outside are opened with [`open_out_stream`], and they
+//! implement [`Write`]. Streams from the outside come from [`IncomingStreams`],
+//! returned by [`install`], which sets this module's processor in `wasm_mp1`.
+//! Bytes of chunks that are queued in incoming streams are limited by maximum
+//! in-flight size, and stream that goes over this limit is cancelled.
+//!
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use crate::wasm_mp1;
+
+/// Kind of stream frame, which is the first byte of a message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+	Chunk = 1,
+	End = 2,
+	Abort = 3,
+	Cancel = 4,
+}
+
+impl FrameKind {
+	fn from_byte(byte: u8) -> Option<Self> {
+		match byte {
+			1 => Some(FrameKind::Chunk),
+			2 => Some(FrameKind::End),
+			3 => Some(FrameKind::Abort),
+			4 => Some(FrameKind::Cancel),
+			_ => None,
+		}
+	}
+}
+
+/// Length of frame's header.
+///
+pub const HEADER_LEN: usize = 5;
+
+/// Default size of chunks, sent by [`OutStream`].
+///
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stream frame, as it is passed in a message. Embedder may use this for its
+/// side of streams.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+	pub kind: FrameKind,
+	pub stream_id: u32,
+	pub body: Vec<u8>,
+}
+
+impl Frame {
+
+	/// Encodes frame into a message.
+	///
+	pub fn encode(&self) -> Vec<u8> {
+		let header = frame_header(self.kind, self.stream_id);
+		let mut msg = Vec::with_capacity(HEADER_LEN + self.body.len());
+		msg.extend_from_slice(&header);
+		msg.extend_from_slice(&self.body);
+		msg
+	}
+
+	/// Decodes frame from a message, returning `None` for messages that are
+	/// not stream frames.
+	///
+	pub fn decode(mut msg: Vec<u8>) -> Option<Self> {
+		if msg.len() < HEADER_LEN {
+			return None;
+		}
+		let kind = FrameKind::from_byte(msg[0])?;
+		let stream_id = u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]]);
+		msg.drain(..HEADER_LEN);
+		Some(Frame { kind, stream_id, body: msg })
+	}
+
+}
+
+/// Piece of payload of incoming stream.
+///
+pub type Chunk = Vec<u8>;
+
+/// Error that ends incoming stream.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+	/// The outside aborted stream with reason, given here.
+	Aborted(Vec<u8>),
+	/// Stream went over maximum in-flight size, and it has been cancelled.
+	Overflow,
+	/// Processor was removed from `wasm_mp1`, or streams were installed
+	/// again, before stream ended.
+	Closed,
+}
+
+impl fmt::Display for StreamError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StreamError::Aborted(reason) => write!(
+				f, "stream was aborted by the other side with {} bytes of reason",
+				reason.len()
+			),
+			StreamError::Overflow => write!(
+				f, "stream went over maximum in-flight size"
+			),
+			StreamError::Closed => write!(f, "stream was closed before its end"),
+		}
+	}
+}
+
+impl std::error::Error for StreamError {}
+
+struct InState {
+	chunks: VecDeque<Chunk>,
+	end: Option<Result<(), StreamError>>,
+	waker: Option<Waker>,
+}
+
+impl InState {
+	fn new() -> Self {
+		InState { chunks: VecDeque::new(), end: None, waker: None }
+	}
+}
+
+struct Streams {
+	next_out_id: u32,
+	/// States of streams to the outside, telling if stream is cancelled.
+	out: HashMap<u32, bool>,
+	generation: u64,
+	max_in_flight: usize,
+	in_flight: usize,
+	incoming: HashMap<u32, InState>,
+	/// Incoming streams, cancelled by this side, which frames are ignored
+	/// till the outside aborts or ends them.
+	cancelled: HashSet<u32>,
+	accept_queue: VecDeque<InStream>,
+	accept_waker: Option<Waker>,
+	is_accepting: bool,
+	is_closed: bool,
+}
+
+impl Streams {
+
+	fn new_out_id(&mut self) -> u32 {
+		loop {
+			let id = self.next_out_id;
+			self.next_out_id = self.next_out_id.wrapping_add(1);
+			if !self.out.contains_key(&id) {
+				return id;
+			}
+		}
+	}
+
+	/// Cancels incoming stream, dropping its queued chunks, and returning
+	/// frame that tells the outside about it.
+	///
+	fn cancel_incoming(&mut self, stream_id: u32) -> (FrameKind, u32, Vec<u8>) {
+		if let Some(state) = self.incoming.get_mut(&stream_id) {
+			let queued: usize = state.chunks.drain(..).map(|chunk| chunk.len()).sum();
+			self.in_flight -= queued;
+		}
+		self.cancelled.insert(stream_id);
+		(FrameKind::Cancel, stream_id, Vec::new())
+	}
+
+}
+
+thread_local! {
+	static STREAMS: RefCell<Streams> = RefCell::new(Streams {
+		next_out_id: 0,
+		out: HashMap::new(),
+		generation: 0,
+		max_in_flight: 0,
+		in_flight: 0,
+		incoming: HashMap::new(),
+		cancelled: HashSet::new(),
+		accept_queue: VecDeque::new(),
+		accept_waker: None,
+		is_accepting: false,
+		is_closed: true,
+	});
+}
+
+fn frame_header(kind: FrameKind, stream_id: u32) -> [u8; HEADER_LEN] {
+	let id = stream_id.to_le_bytes();
+	[kind as u8, id[0], id[1], id[2], id[3]]
+}
+
+fn send_frame(
+	kind: FrameKind, stream_id: u32, body: &[u8]
+) -> Result<(), wasm_mp1::SendError> {
+	wasm_mp1::try_send_parts(&[&frame_header(kind, stream_id), body])
+}
+
+/// Part of processor that closes incoming streams, when processor is dropped.
+///
+struct StreamsFeeder {
+	generation: u64,
+}
+
+impl StreamsFeeder {
+	fn feed(&self, msg: Vec<u8>) {
+		process_msg(msg);
+	}
+}
+
+impl Drop for StreamsFeeder {
+	fn drop(&mut self) {
+		let (wakers, unaccepted) = STREAMS.with(|streams| {
+			let mut streams = streams.borrow_mut();
+			if streams.generation != self.generation {
+				return (Vec::new(), VecDeque::new());
+			}
+			let streams = &mut *streams;
+			streams.is_closed = true;
+			let wakers = streams.incoming.values_mut()
+			.filter_map(|state| state.waker.take())
+			.chain(streams.accept_waker.take())
+			.collect::<Vec<_>>();
+			(wakers, mem::take(&mut streams.accept_queue))
+		});
+		// unaccepted streams are dropped outside of borrow
+		drop(unaccepted);
+		wakers.into_iter().for_each(Waker::wake);
+	}
+}
+
+/// Sets stream processor of messages in `wasm_mp1`, replacing whatever
+/// processor was there before, and returns stream of incoming streams.
+///
+/// At most `max_in_flight` bytes of chunks are held in incoming streams,
+/// waiting to be consumed. Stream, which chunk doesn't fit, is cancelled.
+///
+pub fn install(max_in_flight: usize) -> IncomingStreams {
+	let (generation, wakers, unaccepted) = STREAMS.with(|streams| {
+		let mut streams = streams.borrow_mut();
+		let streams = &mut *streams;
+		// streams of previous generation are closed, and their tasks are woken
+		let wakers = streams.incoming.drain()
+		.filter_map(|(_, state)| state.waker)
+		.chain(streams.accept_waker.take())
+		.collect::<Vec<_>>();
+		let unaccepted = mem::take(&mut streams.accept_queue);
+		streams.generation += 1;
+		streams.max_in_flight = max_in_flight;
+		streams.in_flight = 0;
+		streams.cancelled.clear();
+		streams.is_accepting = true;
+		streams.is_closed = false;
+		(streams.generation, wakers, unaccepted)
+	});
+	// unaccepted streams are dropped outside of borrow
+	drop(unaccepted);
+	wakers.into_iter().for_each(Waker::wake);
+	let feeder = StreamsFeeder { generation };
+	wasm_mp1::set_msg_processor(move |msg| feeder.feed(msg));
+	IncomingStreams { generation }
+}
+
+/// Processes message from the outside as a stream frame. Messages that are
+/// not stream frames are ignored.
+///
+pub fn process_msg(msg: Vec<u8>) {
+	let Some(frame) = Frame::decode(msg) else {
+		return;
+	};
+	let mut reply = None;
+	let mut accept_waker = None;
+	let waker = STREAMS.with(|streams| {
+		let mut streams = streams.borrow_mut();
+		let streams = &mut *streams;
+		let id = frame.stream_id;
+		if frame.kind == FrameKind::Cancel {
+			if let Some(is_cancelled) = streams.out.get_mut(&id) {
+				if !*is_cancelled {
+					*is_cancelled = true;
+					reply = Some((FrameKind::Abort, id, Vec::new()));
+				}
+			}
+			return None;
+		}
+		if streams.is_closed {
+			return None;
+		}
+		if streams.cancelled.contains(&id) {
+			if frame.kind != FrameKind::Chunk {
+				streams.cancelled.remove(&id);
+			}
+			return None;
+		}
+		if !streams.incoming.contains_key(&id) {
+			if !streams.is_accepting {
+				if frame.kind == FrameKind::Chunk {
+					reply = Some(streams.cancel_incoming(id));
+				}
+				return None;
+			}
+			streams.incoming.insert(id, InState::new());
+			let generation = streams.generation;
+			streams.accept_queue.push_back(InStream {
+				stream_id: id, generation, is_closing_reported: false
+			});
+			accept_waker = streams.accept_waker.take();
+		}
+		let fits = streams.in_flight.saturating_add(frame.body.len())
+		<= streams.max_in_flight;
+		let state = streams.incoming.get_mut(&id).unwrap();
+		if state.end.is_some() {
+			return None;
+		}
+		match frame.kind {
+			FrameKind::Chunk if fits => {
+				streams.in_flight += frame.body.len();
+				state.chunks.push_back(frame.body);
+			},
+			FrameKind::Chunk => {
+				state.end = Some(Err(StreamError::Overflow));
+				reply = Some(streams.cancel_incoming(id));
+			},
+			FrameKind::End => {
+				state.end = Some(Ok(()));
+			},
+			FrameKind::Abort => {
+				state.end = Some(Err(StreamError::Aborted(frame.body)));
+			},
+			FrameKind::Cancel => unreachable!(),
+		}
+		streams.incoming.get_mut(&id).and_then(|state| state.waker.take())
+	});
+	// wakers are woken outside of borrow, as they may touch streams
+	for waker in accept_waker.into_iter().chain(waker) {
+		waker.wake();
+	}
+	if let Some((kind, stream_id, body)) = reply {
+		let _ = send_frame(kind, stream_id, &body);
+	}
+}
+
+/// Stream of streams from the outside, created by [`install`].
+///
+pub struct IncomingStreams {
+	generation: u64,
+}
+
+impl Stream for IncomingStreams {
+	type Item = InStream;
+
+	fn poll_next(
+		self: Pin<&mut Self>, cx: &mut Context<'_>
+	) -> Poll<Option<Self::Item>> {
+		STREAMS.with(|streams| {
+			let mut streams = streams.borrow_mut();
+			if streams.generation != self.generation {
+				Poll::Ready(None)
+			} else if let Some(stream) = streams.accept_queue.pop_front() {
+				Poll::Ready(Some(stream))
+			} else if streams.is_closed {
+				Poll::Ready(None)
+			} else {
+				streams.accept_waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		})
+	}
+}
+
+impl Drop for IncomingStreams {
+	fn drop(&mut self) {
+		// thread local may be gone, when thread ends
+		let unaccepted = STREAMS.try_with(|streams| {
+			let mut streams = streams.borrow_mut();
+			if streams.generation != self.generation {
+				return VecDeque::new();
+			}
+			streams.is_accepting = false;
+			mem::take(&mut streams.accept_queue)
+		});
+		// unaccepted streams are cancelled outside of borrow
+		drop(unaccepted);
+	}
+}
+
+/// Incoming stream of chunks from the outside. Stream ends after end frame,
+/// or with [`StreamError`]. Dropping unfinished stream cancels it.
+///
+pub struct InStream {
+	stream_id: u32,
+	generation: u64,
+	/// Tells that closing of stream by a later [`install`] has been reported.
+	is_closing_reported: bool,
+}
+
+impl InStream {
+
+	pub fn stream_id(&self) -> u32 {
+		self.stream_id
+	}
+
+}
+
+impl Stream for InStream {
+	type Item = Result<Chunk, StreamError>;
+
+	fn poll_next(
+		mut self: Pin<&mut Self>, cx: &mut Context<'_>
+	) -> Poll<Option<Self::Item>> {
+		STREAMS.with(|streams| {
+			let mut streams = streams.borrow_mut();
+			if streams.generation != self.generation {
+				return if mem::replace(&mut self.is_closing_reported, true) {
+					Poll::Ready(None)
+				} else {
+					Poll::Ready(Some(Err(StreamError::Closed)))
+				};
+			}
+			let is_closed = streams.is_closed;
+			let Some(state) = streams.incoming.get_mut(&self.stream_id) else {
+				return Poll::Ready(None);
+			};
+			if let Some(chunk) = state.chunks.pop_front() {
+				streams.in_flight -= chunk.len();
+				return Poll::Ready(Some(Ok(chunk)));
+			}
+			match state.end.as_mut() {
+				Some(Ok(())) => Poll::Ready(None),
+				Some(end) => match mem::replace(end, Ok(())) {
+					Err(err) => Poll::Ready(Some(Err(err))),
+					Ok(()) => Poll::Ready(None),
+				},
+				None if is_closed => {
+					state.end = Some(Ok(()));
+					Poll::Ready(Some(Err(StreamError::Closed)))
+				},
+				None => {
+					state.waker = Some(cx.waker().clone());
+					Poll::Pending
+				},
+			}
+		})
+	}
+}
+
+impl Drop for InStream {
+	fn drop(&mut self) {
+		// thread local may be gone, when thread ends
+		let cancel = STREAMS.try_with(|streams| {
+			let mut streams = streams.borrow_mut();
+			if streams.generation != self.generation {
+				return None;
+			}
+			let is_closed = streams.is_closed;
+			let state = streams.incoming.remove(&self.stream_id)?;
+			let queued: usize = state.chunks.iter().map(|chunk| chunk.len()).sum();
+			streams.in_flight -= queued;
+			if state.end.is_none() && !is_closed {
+				Some(streams.cancel_incoming(self.stream_id))
+			} else {
+				None
+			}
+		}).ok().flatten();
+		if let Some((kind, stream_id, body)) = cancel {
+			let _ = send_frame(kind, stream_id, &body);
+		}
+	}
+}
+
+/// Opens stream to the outside with [`DEFAULT_CHUNK_SIZE`].
+///
+pub fn open_out_stream() -> OutStream {
+	open_out_stream_with_chunk_size(DEFAULT_CHUNK_SIZE)
+}
+
+/// Opens stream to the outside, which sends written bytes in chunks of given
+/// size (at least one byte).
+///
+pub fn open_out_stream_with_chunk_size(chunk_size: usize) -> OutStream {
+	let stream_id = STREAMS.with(|streams| {
+		let mut streams = streams.borrow_mut();
+		let id = streams.new_out_id();
+		streams.out.insert(id, false);
+		id
+	});
+	let chunk_size = chunk_size.max(1);
+	OutStream {
+		stream_id,
+		chunk_size,
+		buffer: Vec::with_capacity(chunk_size),
+		is_done: false,
+	}
+}
+
+/// Stream to the outside, created by [`open_out_stream`]. Written bytes are
+/// buffered and sent as chunks.
+///
+/// Stream is completed with [`OutStream::finish`], or given up with
+/// [`OutStream::abort`]. Dropping stream without either of these aborts it.
+/// Writes fail with [`io::ErrorKind::ConnectionAborted`], when the outside
+/// cancels stream. Write, that fails to send a chunk, takes none of given
+/// bytes, and they can be written again.
+///
+pub struct OutStream {
+	stream_id: u32,
+	chunk_size: usize,
+	buffer: Vec<u8>,
+	is_done: bool,
+}
+
+impl OutStream {
+
+	pub fn stream_id(&self) -> u32 {
+		self.stream_id
+	}
+
+	fn is_cancelled(&self) -> bool {
+		STREAMS.with(|streams| {
+			streams.borrow().out.get(&self.stream_id).copied().unwrap_or(true)
+		})
+	}
+
+	fn check_not_cancelled(&self) -> io::Result<()> {
+		if self.is_cancelled() {
+			Err(io::Error::new(
+				io::ErrorKind::ConnectionAborted, "stream is cancelled by the outside"
+			))
+		} else {
+			Ok(())
+		}
+	}
+
+	fn send(&self, kind: FrameKind, body: &[u8]) -> io::Result<()> {
+		self.check_not_cancelled()?;
+		send_frame(kind, self.stream_id, body).map_err(io::Error::other)
+	}
+
+	fn send_buffer(&mut self) -> io::Result<()> {
+		if !self.buffer.is_empty() {
+			self.send(FrameKind::Chunk, &self.buffer)?;
+			self.buffer.clear();
+		}
+		Ok(())
+	}
+
+	/// Sends buffered bytes and end of stream.
+	///
+	pub fn finish(mut self) -> io::Result<()> {
+		self.send_buffer()?;
+		self.is_done = true;
+		self.send(FrameKind::End, &[])
+	}
+
+	/// Drops buffered bytes and tells the outside that stream is given up with
+	/// given `reason`.
+	///
+	pub fn abort(mut self, reason: &[u8]) -> io::Result<()> {
+		self.is_done = true;
+		self.send(FrameKind::Abort, reason)
+	}
+
+}
+
+impl Write for OutStream {
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.check_not_cancelled()?;
+		let buffered = self.buffer.len();
+		let len = buf.len().min(self.chunk_size - buffered);
+		self.buffer.extend_from_slice(&buf[..len]);
+		if self.buffer.len() == self.chunk_size {
+			if let Err(err) = self.send_buffer() {
+				// bytes are given back, as error says that none are written
+				self.buffer.truncate(buffered);
+				return Err(err);
+			}
+		}
+		Ok(len)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.send_buffer()
+	}
+
+}
+
+impl Drop for OutStream {
+	fn drop(&mut self) {
+		// thread local may be gone, when thread ends
+		let was_cancelled = STREAMS.try_with(|streams| {
+			streams.borrow_mut().out.remove(&self.stream_id)
+		}).ok().flatten();
+		// cancelled stream has been aborted in reply to cancel frame
+		if !self.is_done && (was_cancelled == Some(false)) {
+			let _ = send_frame(FrameKind::Abort, self.stream_id, &[]);
+		}
+	}
+}
@@ -0,0 +1,97 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module checks embedders' implementations of message passing,
+//! version 1, against reference guest, built from this crate.
+//!
+//! Reference guest is made by [`guest::install`], which example
+//! `conformance_guest` calls from its exported `_3nweb_conformance_start`.
+//! Guest answers commands in the first byte of incoming messages:
+//! - empty message is sent back,
+//! - [`Command::Echo`] message is sent back unchanged,
+//! - [`Command::Burst`] is followed by little-endian `u32` number and size of
+//!   messages, that guest sends, each made with [`burst_msg`],
+//! - [`Command::Info`] is answered with command byte, followed by
+//!   little-endian `u32` maximum size of incoming messages (`0` for no limit)
+//!   and bit flags of capabilities, as in versions descriptor,
+//! - [`Command::SendPaths`] payload is sent back with every send function of
+//!   `wasm_mp1`, as described in [`Command::SendPaths`].
+//!
+//! Messages with other commands are ignored.
+//!
+//! Harness in [`harness`] drives embedder, that runs reference guest, through
+//! zero-length messages, messages of maximum size, refusal of larger ones,
+//! large outgoing messages, ordering of many messages, and guest's sends
+//! within embedder's call of `_3nweb_mp1_accept_msg`, reporting deviations.
+//!
+
+pub mod guest;
+
+pub mod harness;
+
+/// Name of function, exported by reference guest, that embedder calls after
+/// instantiation, before sending messages.
+///
+pub const GUEST_START_EXPORT: &str = "_3nweb_conformance_start";
+
+/// Maximum size of incoming messages, that reference guest sets.
+///
+pub const MAX_IN_MSG_SIZE: u32 = 64 * 1024;
+
+/// Command to reference guest, which is the first byte of a message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+	Echo = 1,
+	Burst = 2,
+	Info = 3,
+	/// Guest sends payload, that follows command byte, as four messages:
+	/// with `send_msg_out`, with `try_send_msg_out`, and with `try_send_parts`
+	/// split into three parts, each message prefixed with command byte and
+	/// byte of path (`1`, `2` and `3`), followed by message with command byte,
+	/// zero byte, and little-endian `u32` statuses of both tries.
+	SendPaths = 4,
+}
+
+impl Command {
+	fn from_byte(byte: u8) -> Option<Self> {
+		match byte {
+			1 => Some(Command::Echo),
+			2 => Some(Command::Burst),
+			3 => Some(Command::Info),
+			4 => Some(Command::SendPaths),
+			_ => None,
+		}
+	}
+}
+
+/// Makes message number `seq` of `size` bytes in a burst. Message starts with
+/// little-endian `u32` `seq`, cut to `size`, and the rest of bytes are filled
+/// with a pattern, that depends on `seq`.
+///
+pub fn burst_msg(seq: u32, size: usize) -> Vec<u8> {
+	let mut msg = (0..size)
+	.map(|i| (seq as usize).wrapping_add(i) as u8)
+	.collect::<Vec<_>>();
+	let seq_bytes = seq.to_le_bytes();
+	let seq_len = size.min(4);
+	msg[..seq_len].copy_from_slice(&seq_bytes[..seq_len]);
+	msg
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+	let bytes = bytes.get(at..(at + 4))?;
+	Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -0,0 +1,76 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reference guest, which answers commands of conformance protocol.
+//!
+
+use crate::mp_versions;
+use crate::wasm_mp1::{self, SendError};
+use super::{burst_msg, read_u32, Command, MAX_IN_MSG_SIZE};
+
+/// Sets processor of reference guest in `wasm_mp1`, and limits incoming
+/// messages to [`MAX_IN_MSG_SIZE`].
+///
+pub fn install() {
+	wasm_mp1::set_max_in_msg_size(Some(MAX_IN_MSG_SIZE));
+	wasm_mp1::set_msg_processor(answer_command);
+}
+
+fn answer_command(msg: Vec<u8>) {
+	let Some(&first_byte) = msg.first() else {
+		wasm_mp1::send_msg_out(&msg);
+		return;
+	};
+	match Command::from_byte(first_byte) {
+		Some(Command::Echo) => wasm_mp1::send_msg_out(&msg),
+		Some(Command::Burst) => {
+			if let (Some(count), Some(size)) = (read_u32(&msg, 1), read_u32(&msg, 5)) {
+				for seq in 0..count {
+					wasm_mp1::send_msg_out(&burst_msg(seq, size as usize));
+				}
+			}
+		},
+		Some(Command::Info) => {
+			let versions = mp_versions::supported_versions();
+			let mut info = vec![Command::Info as u8];
+			info.extend_from_slice(&versions.max_msg_size.unwrap_or(0).to_le_bytes());
+			info.extend_from_slice(&versions.capabilities.bits().to_le_bytes());
+			wasm_mp1::send_msg_out(&info);
+		},
+		Some(Command::SendPaths) => {
+			let cmd = Command::SendPaths as u8;
+			let payload = &msg[1..];
+			let mut sent = vec![cmd, 1];
+			sent.extend_from_slice(payload);
+			wasm_mp1::send_msg_out(&sent);
+			sent[1] = 2;
+			let tried = status_of(wasm_mp1::try_send_msg_out(&sent));
+			let (head, tail) = payload.split_at(payload.len() / 2);
+			let parts_tried = status_of(wasm_mp1::try_send_parts(&[&[cmd, 3], head, tail]));
+			let mut statuses = vec![cmd, 0];
+			statuses.extend_from_slice(&tried.to_le_bytes());
+			statuses.extend_from_slice(&parts_tried.to_le_bytes());
+			wasm_mp1::send_msg_out(&statuses);
+		},
+		None => (),
+	}
+}
+
+fn status_of(result: Result<(), SendError>) -> u32 {
+	match result {
+		Ok(()) => SendError::STATUS_OK,
+		Err(err) => err.status(),
+	}
+}
@@ -0,0 +1,348 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Harness, that drives embedder with reference guest through conformance
+//! checks.
+//!
+//! Embedder is plugged in with [`HostUnderTest`] implementation. Engine
+//! modules in `host` start `ConformanceHost`, and [`MockHost`] runs reference
+//! guest in-process on mocked embedding of `testing` module.
+//!
+
+use std::fmt;
+use crate::mp_versions::Capabilities;
+use crate::wasm_mp1::SendError;
+use super::{burst_msg, read_u32, Command};
+
+/// Size of outgoing message in check of large messages.
+///
+pub const LARGE_OUT_MSG_SIZE: usize = 1 << 20;
+
+/// Number of messages in checks of ordering.
+///
+pub const NUM_OF_ORDERED_MSGS: u32 = 1000;
+
+/// Embedder, that runs reference guest, as it is seen by harness.
+///
+pub trait HostUnderTest {
+
+	/// Error of sending message into guest.
+	type Error: fmt::Display;
+
+	/// Sends message into guest, as embedder does with `_3nweb_mp1_accept_msg`.
+	/// Error is returned, when guest refuses message, or when call fails.
+	///
+	fn send(&mut self, msg: Vec<u8>) -> Result<(), Self::Error>;
+
+	/// Takes messages, that guest has sent since previous call, in order of
+	/// their sending.
+	///
+	fn take_out_msgs(&mut self) -> Vec<Vec<u8>>;
+
+}
+
+/// Deviation of embedder from expected behaviour, found by a check.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deviation {
+	pub check: &'static str,
+	pub details: String,
+}
+
+impl fmt::Display for Deviation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}: {}", self.check, self.details)
+	}
+}
+
+/// Outcomes of conformance checks.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+	/// Capabilities, reported by guest, which tell handshake and send imports,
+	/// that were checked.
+	pub guest_capabilities: Option<Capabilities>,
+	pub passed: Vec<&'static str>,
+	/// Checks, that couldn't run, with reasons.
+	pub skipped: Vec<(&'static str, String)>,
+	pub deviations: Vec<Deviation>,
+}
+
+impl Report {
+
+	/// Tells if no deviations were found.
+	///
+	pub fn is_conformant(&self) -> bool {
+		self.deviations.is_empty()
+	}
+
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if let Some(capabilities) = self.guest_capabilities {
+			writeln!(f, "guest capabilities: {:#x}", capabilities.bits())?;
+		}
+		for check in self.passed.iter() {
+			writeln!(f, "passed   {check}")?;
+		}
+		for (check, reason) in self.skipped.iter() {
+			writeln!(f, "skipped  {check}: {reason}")?;
+		}
+		for deviation in self.deviations.iter() {
+			writeln!(f, "DEVIATES {deviation}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Result of a check, which is `Err` with details of deviation.
+///
+type Verdict = Result<Passed, String>;
+
+enum Passed {
+	Yes,
+	Skipped(String),
+}
+
+/// Runs all conformance checks with given embedder, which should have just
+/// started reference guest.
+///
+pub fn run(host: &mut impl HostUnderTest) -> Report {
+	let info = run_check(host, read_info);
+	let max_in_msg_size = info.as_ref().ok().and_then(|(max_size, _)| *max_size);
+	let mut report = Report {
+		guest_capabilities: info.as_ref().ok().map(|(_, capabilities)| *capabilities),
+		..Report::default()
+	};
+	let mut record = |check: &'static str, verdict: Verdict| match verdict {
+		Ok(Passed::Yes) => report.passed.push(check),
+		Ok(Passed::Skipped(reason)) => report.skipped.push((check, reason)),
+		Err(details) => report.deviations.push(Deviation { check, details }),
+	};
+	record("info", info.map(|_| Passed::Yes));
+	record("zero-length message", run_check(host, check_zero_length));
+	record("echo", run_check(host, check_echo));
+	record("max size message", run_check(host, |host| {
+		check_max_size(host, max_in_msg_size)
+	}));
+	record("large outgoing message", run_check(host, check_large_out_msg));
+	record("ordering of burst", run_check(host, check_burst_order));
+	record("ordering of sends into guest", run_check(host, check_sends_order));
+	record("sends within accept call", run_check(host, check_send_paths));
+	report
+}
+
+/// Runs a check, discarding messages, that are left by previous one.
+///
+fn run_check<H: HostUnderTest, T>(
+	host: &mut H, check: impl FnOnce(&mut H) -> Result<T, String>
+) -> Result<T, String> {
+	host.take_out_msgs();
+	check(host)
+}
+
+fn send<H: HostUnderTest>(host: &mut H, msg: Vec<u8>) -> Result<(), String> {
+	let len = msg.len();
+	host.send(msg).map_err(|err| format!("sending {len} bytes into guest failed: {err}"))
+}
+
+/// Sends message and takes messages, that guest sends in answer.
+///
+fn exchange<H: HostUnderTest>(
+	host: &mut H, msg: Vec<u8>
+) -> Result<Vec<Vec<u8>>, String> {
+	send(host, msg)?;
+	Ok(host.take_out_msgs())
+}
+
+fn expect_msgs(received: &[Vec<u8>], expected: &[Vec<u8>]) -> Result<(), String> {
+	if received.len() != expected.len() {
+		return Err(format!(
+			"expected {} messages from guest, but got {}",
+			expected.len(), received.len()
+		));
+	}
+	match received.iter().zip(expected.iter()).position(|(r, e)| r != e) {
+		Some(i) => Err(format!(
+			"message {i} from guest has {} bytes, differing from expected {} bytes",
+			received[i].len(), expected[i].len()
+		)),
+		None => Ok(()),
+	}
+}
+
+fn echo_msg(len: usize) -> Vec<u8> {
+	let mut msg = burst_msg(len as u32, len);
+	if let Some(first_byte) = msg.first_mut() {
+		*first_byte = Command::Echo as u8;
+	}
+	msg
+}
+
+fn check_zero_length<H: HostUnderTest>(host: &mut H) -> Verdict {
+	let received = exchange(host, Vec::new())?;
+	expect_msgs(&received, &[Vec::new()])?;
+	Ok(Passed::Yes)
+}
+
+fn check_echo<H: HostUnderTest>(host: &mut H) -> Verdict {
+	for len in [1, 2, 5, 100, 4096] {
+		let msg = echo_msg(len);
+		let received = exchange(host, msg.clone())?;
+		expect_msgs(&received, &[msg])?;
+	}
+	Ok(Passed::Yes)
+}
+
+/// Asks guest for its maximum size of incoming messages and capabilities.
+///
+fn read_info<H: HostUnderTest>(
+	host: &mut H
+) -> Result<(Option<u32>, Capabilities), String> {
+	let received = exchange(host, vec![Command::Info as u8])?;
+	let malformed = || "guest's answer to info command is lost or malformed".to_string();
+	let [info] = received.as_slice() else {
+		return Err(malformed());
+	};
+	if (info.len() != 9) || (info[0] != Command::Info as u8) {
+		return Err(malformed());
+	}
+	let max_size = read_u32(info, 1).filter(|&max_size| max_size > 0);
+	let capabilities = Capabilities::from_bits(read_u32(info, 5).unwrap_or(0));
+	Ok((max_size, capabilities))
+}
+
+fn check_max_size<H: HostUnderTest>(host: &mut H, max_size: Option<u32>) -> Verdict {
+	let Some(max_size) = max_size else {
+		return Ok(Passed::Skipped("guest has no maximum size".to_string()));
+	};
+	let msg = echo_msg(max_size as usize);
+	let received = exchange(host, msg.clone())?;
+	expect_msgs(&received, &[msg])?;
+	let too_large = echo_msg(max_size as usize + 1);
+	if host.send(too_large).is_ok() {
+		return Err("message above guest's maximum size isn't reported as refused".to_string());
+	}
+	let received = host.take_out_msgs();
+	if !received.is_empty() {
+		return Err("message above guest's maximum size has reached guest".to_string());
+	}
+	let msg = echo_msg(10);
+	let received = exchange(host, msg.clone())
+	.map_err(|err| format!("after refusal, {err}"))?;
+	expect_msgs(&received, &[msg]).map_err(|err| format!("after refusal, {err}"))?;
+	Ok(Passed::Yes)
+}
+
+fn burst_cmd(count: u32, size: usize) -> Vec<u8> {
+	let mut cmd = vec![Command::Burst as u8];
+	cmd.extend_from_slice(&count.to_le_bytes());
+	cmd.extend_from_slice(&(size as u32).to_le_bytes());
+	cmd
+}
+
+fn check_large_out_msg<H: HostUnderTest>(host: &mut H) -> Verdict {
+	let received = exchange(host, burst_cmd(1, LARGE_OUT_MSG_SIZE))?;
+	expect_msgs(&received, &[burst_msg(0, LARGE_OUT_MSG_SIZE)])?;
+	Ok(Passed::Yes)
+}
+
+fn check_burst_order<H: HostUnderTest>(host: &mut H) -> Verdict {
+	let received = exchange(host, burst_cmd(NUM_OF_ORDERED_MSGS, 16))?;
+	let expected = (0..NUM_OF_ORDERED_MSGS)
+	.map(|seq| burst_msg(seq, 16))
+	.collect::<Vec<_>>();
+	expect_msgs(&received, &expected)?;
+	Ok(Passed::Yes)
+}
+
+fn check_sends_order<H: HostUnderTest>(host: &mut H) -> Verdict {
+	let expected = (0..NUM_OF_ORDERED_MSGS)
+	.map(|seq| {
+		let mut msg = vec![Command::Echo as u8];
+		msg.extend_from_slice(&seq.to_le_bytes());
+		msg
+	})
+	.collect::<Vec<_>>();
+	for msg in expected.iter() {
+		send(host, msg.clone())?;
+	}
+	expect_msgs(&host.take_out_msgs(), &expected)?;
+	Ok(Passed::Yes)
+}
+
+fn check_send_paths<H: HostUnderTest>(host: &mut H) -> Verdict {
+	let payload = burst_msg(7, 300);
+	let mut cmd = vec![Command::SendPaths as u8];
+	cmd.extend_from_slice(&payload);
+	let received = exchange(host, cmd)?;
+	let expected = (1..=3)
+	.map(|path| {
+		let mut msg = vec![Command::SendPaths as u8, path];
+		msg.extend_from_slice(&payload);
+		msg
+	})
+	.collect::<Vec<_>>();
+	let Some((statuses, sent)) = received.split_last() else {
+		return Err("guest's messages are lost".to_string());
+	};
+	expect_msgs(sent, &expected)?;
+	let tried = read_u32(statuses, 2);
+	let parts_tried = read_u32(statuses, 6);
+	if (tried, parts_tried) != (Some(SendError::STATUS_OK), Some(SendError::STATUS_OK)) {
+		return Err(format!(
+			"guest's sends got statuses {tried:?} and {parts_tried:?}, instead of \
+			taking messages"
+		));
+	}
+	Ok(Passed::Yes)
+}
+
+/// Reference guest, running in-process on mocked embedding of `testing`
+/// module. It checks reference guest and harness, rather than an embedder.
+///
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+pub struct MockHost;
+
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+impl MockHost {
+
+	/// Starts reference guest in this thread, replacing its message processor.
+	///
+	pub fn start() -> Self {
+		super::guest::install();
+		crate::testing::take_sent_msgs();
+		MockHost
+	}
+
+}
+
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+impl HostUnderTest for MockHost {
+	type Error = &'static str;
+
+	fn send(&mut self, msg: Vec<u8>) -> Result<(), Self::Error> {
+		if crate::testing::inject_msg(msg) {
+			Ok(())
+		} else {
+			Err("guest refused message")
+		}
+	}
+
+	fn take_out_msgs(&mut self) -> Vec<Vec<u8>> {
+		crate::testing::take_sent_msgs()
+	}
+}
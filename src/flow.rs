@@ -0,0 +1,364 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module adds credit-based flow control on top of message passing,
+//! version 1, so that neither side sends more messages than the other side
+//! has agreed to take.
+//!
+//! Every message starts with a byte of frame kind. Data frame carries message
+//! in the rest of bytes. Credit frame carries little-endian `u32` number of
+//! messages that its sender grants to the other side. Each side sends one data
+//! frame per credit, and grants credits back as it processes messages.
+//!
+//! [`install`] sets this module's processor in `wasm_mp1` and grants initial
+//! window of credits to the outside. Messages are sent with [`try_send`],
+//! which returns [`FlowError::WouldBlock`] without credits, or with [`send`],
+//! which future waits for credits. Data frames that come from the outside
+//! without credits are dropped and counted.
+//!
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use crate::wasm_mp1::{self, SendError};
+
+/// Kind of flow control frame, which is the first byte of a message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+	Data = 1,
+	Credit = 2,
+}
+
+/// Flow control frame, as it is passed in a message. Embedder may use this
+/// for its side of flow control.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+	/// Message, which takes one credit.
+	Data(Vec<u8>),
+	/// Number of messages, granted to the other side.
+	Credit(u32),
+}
+
+impl Frame {
+
+	/// Encodes frame into a message.
+	///
+	pub fn encode(&self) -> Vec<u8> {
+		match self {
+			Frame::Data(body) => {
+				let mut msg = Vec::with_capacity(1 + body.len());
+				msg.push(FrameKind::Data as u8);
+				msg.extend_from_slice(body);
+				msg
+			},
+			Frame::Credit(credits) => credit_msg(*credits).to_vec(),
+		}
+	}
+
+	/// Decodes frame from a message, returning `None` for messages that are
+	/// not flow control frames.
+	///
+	pub fn decode(mut msg: Vec<u8>) -> Option<Self> {
+		match msg.first().copied() {
+			Some(kind) if kind == FrameKind::Data as u8 => {
+				msg.remove(0);
+				Some(Frame::Data(msg))
+			},
+			Some(kind) if (kind == FrameKind::Credit as u8) && (msg.len() == 5) => {
+				Some(Frame::Credit(u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]])))
+			},
+			_ => None,
+		}
+	}
+
+}
+
+/// Error of sending message with flow control.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowError {
+	/// The outside hasn't granted credits for more messages.
+	WouldBlock,
+	/// Embedder has rejected message.
+	Rejected(SendError),
+}
+
+impl fmt::Display for FlowError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FlowError::WouldBlock => write!(f, "no credits to send message"),
+			FlowError::Rejected(err) => err.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for FlowError {}
+
+struct Flow {
+	/// Credits, granted by the outside, for sending messages.
+	send_credits: u32,
+	/// Tickets of [`SendWhenReady`] futures, waiting for credits in order.
+	waiting: VecDeque<(u64, Option<Waker>)>,
+	next_ticket: u64,
+	/// The first ticket after the last [`install`]. Futures with earlier
+	/// tickets have waited in previous flow, and they are closed.
+	first_ticket: u64,
+	/// Number of processed messages that are granted back in one credit frame.
+	grant_batch: u32,
+	/// Credits that the outside has for sending messages here.
+	recv_credits: u32,
+	/// Processed messages, which credits aren't granted back yet.
+	processed: u32,
+	overruns: u64,
+}
+
+thread_local! {
+	static FLOW: RefCell<Flow> = const { RefCell::new(Flow {
+		send_credits: 0,
+		waiting: VecDeque::new(),
+		next_ticket: 0,
+		first_ticket: 0,
+		grant_batch: 1,
+		recv_credits: 0,
+		processed: 0,
+		overruns: 0,
+	}) };
+}
+
+fn credit_msg(credits: u32) -> [u8; 5] {
+	let c = credits.to_le_bytes();
+	[FrameKind::Credit as u8, c[0], c[1], c[2], c[3]]
+}
+
+fn send_data(msg: &[u8]) -> Result<(), SendError> {
+	wasm_mp1::try_send_parts(&[&[FrameKind::Data as u8], msg])
+}
+
+/// Sets flow control processor of messages in `wasm_mp1`, replacing whatever
+/// processor was there before, and grants `window` credits to the outside.
+///
+/// Messages from data frames are given to `processor`. Credits are granted
+/// back in batches of half a window, as messages are processed.
+///
+/// Flow starts anew, without credits from the outside, and [`send`] futures,
+/// waiting in previous flow, fail with [`SendError::Closed`].
+///
+pub fn install(window: u32, mut processor: impl FnMut(Vec<u8>) + 'static) {
+	let window = window.max(1);
+	let closed = FLOW.with(|flow| {
+		let mut flow = flow.borrow_mut();
+		let next_ticket = flow.next_ticket;
+		let previous = std::mem::replace(&mut *flow, Flow {
+			send_credits: 0,
+			waiting: VecDeque::new(),
+			next_ticket,
+			first_ticket: next_ticket,
+			grant_batch: window.div_ceil(2),
+			recv_credits: window,
+			processed: 0,
+			overruns: 0,
+		});
+		previous.waiting
+	});
+	// closed futures are woken outside of borrow, as they poll flow
+	closed.into_iter()
+	.filter_map(|(_, waker)| waker)
+	.for_each(Waker::wake);
+	wasm_mp1::set_msg_processor(move |msg| match Frame::decode(msg) {
+		Some(Frame::Data(body)) if take_recv_credit() => {
+			processor(body);
+			grant_back();
+		},
+		Some(Frame::Credit(credits)) => add_send_credits(credits),
+		_ => (),
+	});
+	let _ = wasm_mp1::try_send_msg_out(&credit_msg(window));
+}
+
+fn take_recv_credit() -> bool {
+	FLOW.with(|flow| {
+		let mut flow = flow.borrow_mut();
+		if flow.recv_credits == 0 {
+			flow.overruns += 1;
+			false
+		} else {
+			flow.recv_credits -= 1;
+			true
+		}
+	})
+}
+
+fn grant_back() {
+	let grant = FLOW.with(|flow| {
+		let mut flow = flow.borrow_mut();
+		flow.processed += 1;
+		if flow.processed >= flow.grant_batch {
+			let credits = std::mem::take(&mut flow.processed);
+			flow.recv_credits += credits;
+			Some(credits)
+		} else {
+			None
+		}
+	});
+	if let Some(credits) = grant {
+		let _ = wasm_mp1::try_send_msg_out(&credit_msg(credits));
+	}
+}
+
+fn add_send_credits(credits: u32) {
+	let waker = FLOW.with(|flow| {
+		let mut flow = flow.borrow_mut();
+		flow.send_credits = flow.send_credits.saturating_add(credits);
+		flow.waiting.front_mut().and_then(|(_, waker)| waker.take())
+	});
+	if let Some(waker) = waker {
+		waker.wake();
+	}
+}
+
+/// Sends message to the outside in a data frame, if there is a credit for
+/// it. Messages, waiting in [`send`] futures, go first.
+///
+pub fn try_send(msg: &[u8]) -> Result<(), FlowError> {
+	let has_credit = FLOW.with(|flow| {
+		let mut flow = flow.borrow_mut();
+		if (flow.send_credits > 0) && flow.waiting.is_empty() {
+			flow.send_credits -= 1;
+			true
+		} else {
+			false
+		}
+	});
+	if has_credit {
+		send_data(msg).map_err(FlowError::Rejected)
+	} else {
+		Err(FlowError::WouldBlock)
+	}
+}
+
+/// Returns future that sends message to the outside in a data frame, when
+/// there is a credit for it. Futures send their messages in order of their
+/// first polling. Future fails with [`SendError::Closed`], when [`install`] is
+/// called again, before its message is sent.
+///
+pub fn send(msg: Vec<u8>) -> SendWhenReady {
+	SendWhenReady { msg, ticket: None }
+}
+
+/// Returns number of credits for sending messages to the outside.
+///
+pub fn send_credits() -> u32 {
+	FLOW.with(|flow| flow.borrow().send_credits)
+}
+
+/// Returns number of data frames from the outside that have been dropped,
+/// as they came without credits.
+///
+pub fn overrun_count() -> u64 {
+	FLOW.with(|flow| flow.borrow().overruns)
+}
+
+/// Future of sending a message with flow control, created by [`send`].
+///
+pub struct SendWhenReady {
+	msg: Vec<u8>,
+	ticket: Option<u64>,
+}
+
+impl Future for SendWhenReady {
+	type Output = Result<(), SendError>;
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let (turn, next_waker) = FLOW.with(|flow| {
+			let mut flow = flow.borrow_mut();
+			let ticket = match self.ticket {
+				Some(ticket) if ticket < flow.first_ticket => {
+					return (Turn::Closed, None);
+				},
+				Some(ticket) => ticket,
+				None => {
+					let ticket = flow.next_ticket;
+					flow.next_ticket += 1;
+					flow.waiting.push_back((ticket, None));
+					self.ticket = Some(ticket);
+					ticket
+				},
+			};
+			let is_first = flow.waiting.front().map(|&(t, _)| t) == Some(ticket);
+			if is_first && (flow.send_credits > 0) {
+				flow.send_credits -= 1;
+				flow.waiting.pop_front();
+				self.ticket = None;
+				let next_waker = if flow.send_credits > 0 {
+					flow.waiting.front_mut().and_then(|(_, waker)| waker.take())
+				} else {
+					None
+				};
+				(Turn::Send, next_waker)
+			} else {
+				if let Some(entry) = flow.waiting.iter_mut().find(|(t, _)| *t == ticket) {
+					entry.1 = Some(cx.waker().clone());
+				}
+				(Turn::Wait, None)
+			}
+		});
+		if let Some(waker) = next_waker {
+			waker.wake();
+		}
+		match turn {
+			Turn::Send => Poll::Ready(send_data(&self.msg)),
+			Turn::Wait => Poll::Pending,
+			Turn::Closed => {
+				self.ticket = None;
+				Poll::Ready(Err(SendError::Closed))
+			},
+		}
+	}
+}
+
+/// Outcome of polling [`SendWhenReady`] in flow's state.
+///
+enum Turn {
+	Send,
+	Wait,
+	Closed,
+}
+
+impl Drop for SendWhenReady {
+	fn drop(&mut self) {
+		let Some(ticket) = self.ticket else {
+			return;
+		};
+		// thread local may be gone, when thread ends
+		let next_waker = FLOW.try_with(|flow| {
+			let mut flow = flow.borrow_mut();
+			let was_first = flow.waiting.front().map(|&(t, _)| t) == Some(ticket);
+			flow.waiting.retain(|&(t, _)| t != ticket);
+			if was_first && (flow.send_credits > 0) {
+				flow.waiting.front_mut().and_then(|(_, waker)| waker.take())
+			} else {
+				None
+			}
+		}).ok().flatten();
+		if let Some(waker) = next_waker {
+			waker.wake();
+		}
+	}
+}
@@ -0,0 +1,661 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module provides embedder (host) side of 3nweb's message passing api,
+//! for running WASM modules outside of 3NWeb client platform.
+//!
+//! State of message passing lives in [`Mp1Ctx`], which should be placed into
+//! (or be) the data of engine's store. Engine specific modules link imports
+//! expected by WASM and drive exports of WASM instance.
+//!
+//! Versions of message passing, supported by WASM module, are read with
+//! engine's `read_versions`, which falls back to probing of exports for
+//! modules without versions descriptor.
+//!
+//! Embedder calls service in WASM instance with the same typed client, that
+//! instance's service trait has, given `ServiceTransport` and
+//! `calls::HostCalls`, which pick replies among instance's messages.
+//!
+//! Engine modules only glue engine's linker, memory and calls to functions of
+//! this module, which read and write WASM's memory as byte slices. Generic
+//! `ServiceTransport` and `ConformanceHost` send messages into engine's
+//! instance with `Mp1Sender`, which engine module implements.
+//!
+
+use std::fmt;
+use std::sync::mpsc;
+use crate::mp_versions::{Capabilities, MP1, MP2, MpVersions};
+use crate::panic_hook::PanicReport;
+use crate::wasm_mp1::SendError;
+#[cfg(feature = "mp2")]
+use crate::wasm_mp2::ring::{PushError, Ring};
+#[cfg(feature = "service")]
+use std::future::Future;
+#[cfg(feature = "service")]
+use crate::rpc::{Frame, FrameKind};
+#[cfg(feature = "service")]
+use crate::service::{ServiceError, Transport};
+#[cfg(feature = "service")]
+use calls::HostCalls;
+#[cfg(feature = "conformance")]
+use crate::conformance::harness::HostUnderTest;
+
+#[cfg(feature = "service")]
+pub mod calls;
+
+#[cfg(feature = "wasmtime")]
+pub mod wasmtime;
+
+#[cfg(feature = "wasmi")]
+pub mod wasmi;
+
+/// Versions of message passing, implemented by this module. Embedder picks
+/// one of them with [`MpVersions::pick`](crate::mp_versions::MpVersions::pick).
+/// Version 2 is implemented with `mp2` feature.
+///
+#[cfg(not(feature = "mp2"))]
+pub const SUPPORTED_VERSIONS: &[u32] = &[ MP1 ];
+
+/// Versions of message passing, implemented by this module. Embedder picks
+/// one of them with [`MpVersions::pick`](crate::mp_versions::MpVersions::pick).
+///
+#[cfg(feature = "mp2")]
+pub const SUPPORTED_VERSIONS: &[u32] = &[ MP1, MP2 ];
+
+/// Callback that gets binary messages, sent by WASM instance to the outside.
+///
+pub type OutMsgHandler = Box<dyn FnMut(Vec<u8>) + Send>;
+
+/// Check of message from WASM instance, which may reject it, before it is
+/// given to [`OutMsgHandler`].
+///
+pub type OutMsgCheck = Box<dyn FnMut(&[u8]) -> Result<(), SendError> + Send>;
+
+/// Embedder's state of message passing, version 1, with a particular WASM
+/// instance.
+///
+/// Messages from WASM instance are rejected when context is closed, when they
+/// are longer than maximum size, and when check rejects them. Rejection is
+/// reported to WASM that uses `_3nweb_mp1_try_send_out_msg`, while messages
+/// through legacy `_3nweb_mp1_send_out_msg` are silently dropped.
+///
+/// Panic, reported by WASM through `_3nweb_mp1_panic` before it traps, is kept
+/// in context, and should be taken with [`Mp1Ctx::take_panic_report`], when
+/// call into instance fails.
+///
+pub struct Mp1Ctx {
+	in_msg: Option<Vec<u8>>,
+	is_in_msg_refused: bool,
+	out_msg_handler: OutMsgHandler,
+	max_out_msg_size: Option<usize>,
+	out_msg_check: Option<OutMsgCheck>,
+	is_closed: bool,
+	panic_report: Option<PanicReport>,
+}
+
+impl Mp1Ctx {
+
+	/// Creates context that gives messages from WASM instance to given
+	/// `out_msg_handler`.
+	///
+	pub fn new(out_msg_handler: impl FnMut(Vec<u8>) + Send + 'static) -> Self {
+		Mp1Ctx {
+			in_msg: None,
+			is_in_msg_refused: false,
+			out_msg_handler: Box::new(out_msg_handler),
+			max_out_msg_size: None,
+			out_msg_check: None,
+			is_closed: false,
+			panic_report: None,
+		}
+	}
+
+	/// Creates context that puts messages from WASM instance into a channel,
+	/// returning receiving end of it.
+	///
+	pub fn with_channel() -> (Self, mpsc::Receiver<Vec<u8>>) {
+		let (sender, receiver) = mpsc::channel();
+		let ctx = Mp1Ctx::new(move |msg| {
+			// receiver may be gone, and then nobody cares about the message
+			let _ = sender.send(msg);
+		});
+		(ctx, receiver)
+	}
+
+	/// Sets maximum size of messages from WASM instance. Longer messages are
+	/// rejected with [`SendError::TooLarge`].
+	///
+	pub fn set_max_out_msg_size(&mut self, max_size: Option<usize>) {
+		self.max_out_msg_size = max_size;
+	}
+
+	/// Sets `check` of messages from WASM instance, which is called after
+	/// size check, and may reject message, for example, with
+	/// [`SendError::OverQuota`].
+	///
+	pub fn set_out_msg_check(
+		&mut self,
+		check: impl FnMut(&[u8]) -> Result<(), SendError> + Send + 'static
+	) {
+		self.out_msg_check = Some(Box::new(check));
+	}
+
+	/// Closes context, so that all following messages from WASM instance are
+	/// rejected with [`SendError::Closed`].
+	///
+	pub fn close(&mut self) {
+		self.is_closed = true;
+	}
+
+	pub fn is_closed(&self) -> bool {
+		self.is_closed
+	}
+
+	/// Takes report of the last panic in WASM instance, if there was one.
+	///
+	pub fn take_panic_report(&mut self) -> Option<PanicReport> {
+		self.panic_report.take()
+	}
+
+	/// Checks closing and size of message, before its bytes are read.
+	///
+	pub(crate) fn check_out_msg_len(&self, len: usize) -> Result<(), SendError> {
+		if self.is_closed {
+			Err(SendError::Closed)
+		} else if self.max_out_msg_size.is_some_and(|max| len > max) {
+			Err(SendError::TooLarge)
+		} else {
+			Ok(())
+		}
+	}
+
+	pub(crate) fn set_panic_report(&mut self, report: PanicReport) {
+		self.panic_report = Some(report);
+	}
+
+	pub(crate) fn set_in_msg(&mut self, msg: Vec<u8>) {
+		self.in_msg = Some(msg);
+		self.is_in_msg_refused = false;
+	}
+
+	pub(crate) fn take_in_msg(&mut self) -> Option<Vec<u8>> {
+		self.in_msg.take()
+	}
+
+	/// Drops incoming message, which WASM has refused with
+	/// `_3nweb_mp1_refuse_msg`.
+	///
+	pub(crate) fn refuse_in_msg(&mut self) {
+		self.in_msg = None;
+		self.is_in_msg_refused = true;
+	}
+
+	/// Tells if WASM has refused incoming message, resetting this flag.
+	///
+	pub(crate) fn take_in_msg_refusal(&mut self) -> bool {
+		std::mem::take(&mut self.is_in_msg_refused)
+	}
+
+	pub(crate) fn deliver_out_msg(&mut self, msg: Vec<u8>) -> Result<(), SendError> {
+		if let Some(check) = self.out_msg_check.as_mut() {
+			check(&msg)?;
+		}
+		(self.out_msg_handler)(msg);
+		Ok(())
+	}
+
+}
+
+/// Gives access to [`Mp1Ctx`] inside of engine's store data.
+///
+pub trait Mp1View {
+	fn mp1_ctx(&mut self) -> &mut Mp1Ctx;
+}
+
+impl Mp1View for Mp1Ctx {
+	fn mp1_ctx(&mut self) -> &mut Mp1Ctx {
+		self
+	}
+}
+
+/// Error of embedding, which traps WASM, when it happens in imported function,
+/// or fails embedder's call into WASM. Engine modules turn it into engine's
+/// error.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HostError(String);
+
+impl HostError {
+	pub(crate) fn new(msg: impl Into<String>) -> Self {
+		HostError(msg.into())
+	}
+}
+
+impl fmt::Display for HostError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+#[cfg(feature = "mp2")]
+impl From<PushError> for HostError {
+	fn from(err: PushError) -> Self {
+		HostError(err.to_string())
+	}
+}
+
+/// Returns `len` bytes of WASM's `memory` at `ptr`, or `None`, when they are
+/// out of memory bounds.
+///
+fn area(memory: &[u8], ptr: usize, len: usize) -> Option<&[u8]> {
+	memory.get(ptr..ptr.checked_add(len)?)
+}
+
+fn area_mut(memory: &mut [u8], ptr: usize, len: usize) -> Option<&mut [u8]> {
+	memory.get_mut(ptr..ptr.checked_add(len)?)
+}
+
+fn out_msg_out_of_bounds() -> HostError {
+	HostError::new("Outgoing message is out of memory bounds")
+}
+
+fn refused_in_msg() -> HostError {
+	HostError::new("WASM instance refused incoming message as too large")
+}
+
+/// Copies message at `ptr` from WASM's `memory` and gives it to `ctx`, which
+/// may reject it. Outer error is for malformed call, that traps.
+///
+pub(crate) fn take_out_msg(
+	memory: &[u8], ctx: &mut Mp1Ctx, ptr: u32, len: u32
+) -> Result<Result<(), SendError>, HostError> {
+	if let Err(err) = ctx.check_out_msg_len(len as usize) {
+		return Ok(Err(err));
+	}
+	let msg = area(memory, ptr as usize, len as usize)
+	.ok_or_else(out_msg_out_of_bounds)?
+	.to_vec();
+	Ok(ctx.deliver_out_msg(msg))
+}
+
+/// Gathers message from areas of WASM's `memory`, which pointers and lengths
+/// are `u32` pairs at `parts_ptr`, and gives it to `ctx`, which may reject
+/// it. Outer error is for malformed call, that traps.
+///
+pub(crate) fn take_out_msg_parts(
+	memory: &[u8], ctx: &mut Mp1Ctx, parts_ptr: u32, num_of_parts: u32
+) -> Result<Result<(), SendError>, HostError> {
+	// all parts are in memory bounds, before anything is allocated for them
+	let parts = (num_of_parts as usize).checked_mul(8)
+	.and_then(|len| area(memory, parts_ptr as usize, len))
+	.ok_or_else(out_msg_out_of_bounds)?
+	.chunks_exact(8)
+	.map(|pair| area(
+		memory, read_u32(pair, 0) as usize, read_u32(pair, 4) as usize
+	).ok_or_else(out_msg_out_of_bounds))
+	.collect::<Result<Vec<_>, _>>()?;
+	let len = parts.iter()
+	.try_fold(0usize, |total, part| total.checked_add(part.len()))
+	.ok_or_else(out_msg_out_of_bounds)?;
+	if let Err(err) = ctx.check_out_msg_len(len) {
+		return Ok(Err(err));
+	}
+	Ok(ctx.deliver_out_msg(parts.concat()))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+	u32::from_le_bytes([bytes[at], bytes[at+1], bytes[at+2], bytes[at+3]])
+}
+
+/// Returns status of sending message, which imports
+/// `_3nweb_mp1_try_send_out_msg` and `_3nweb_mp1_send_out_msg_parts` give to
+/// WASM.
+///
+pub(crate) fn send_status(result: Result<(), SendError>) -> u32 {
+	match result {
+		Ok(()) => SendError::STATUS_OK,
+		Err(err) => err.status(),
+	}
+}
+
+/// Writes incoming message, that WASM asks for with
+/// `_3nweb_mp1_write_msg_into`, into its `memory` at `ptr`.
+///
+pub(crate) fn write_in_msg(
+	memory: &mut [u8], ctx: &mut Mp1Ctx, ptr: u32
+) -> Result<(), HostError> {
+	let msg = ctx.take_in_msg()
+	.ok_or_else(|| HostError::new("There is no incoming message to write"))?;
+	write_msg(memory, ptr, &msg)
+}
+
+fn write_msg(memory: &mut [u8], ptr: u32, msg: &[u8]) -> Result<(), HostError> {
+	area_mut(memory, ptr as usize, msg.len())
+	.ok_or_else(|| HostError::new("Incoming message is out of memory bounds"))?
+	.copy_from_slice(msg);
+	Ok(())
+}
+
+/// Keeps in `ctx` panic report at `ptr` in WASM's `memory`, which WASM gives
+/// to `_3nweb_mp1_panic`. Malformed reports are ignored, as WASM traps right
+/// after them.
+///
+pub(crate) fn keep_panic_report(memory: &[u8], ctx: &mut Mp1Ctx, ptr: u32, len: u32) {
+	if let Some(report) = area(memory, ptr as usize, len as usize)
+	.and_then(PanicReport::decode) {
+		ctx.set_panic_report(report);
+	}
+}
+
+/// Returns length of incoming message, as it is given to
+/// `_3nweb_mp1_accept_msg` and `_3nweb_mp1_get_buffer`.
+///
+pub(crate) fn in_msg_len(msg: &[u8]) -> Result<u32, HostError> {
+	u32::try_from(msg.len())
+	.map_err(|_| HostError::new("Message is too long for 32-bit WASM"))
+}
+
+/// Writes incoming message into WASM's `memory` at `ptr`, returned by
+/// `_3nweb_mp1_get_buffer`, which is zero, when WASM refuses message.
+///
+pub(crate) fn write_in_msg_into_buffer(
+	memory: &mut [u8], ptr: u32, msg: &[u8]
+) -> Result<(), HostError> {
+	if ptr == 0 {
+		return Err(refused_in_msg());
+	}
+	write_msg(memory, ptr, msg)
+}
+
+/// Tells, after `_3nweb_mp1_accept_msg` call, if WASM has taken incoming
+/// message with `_3nweb_mp1_write_msg_into` callback, clearing message and
+/// its refusal in `ctx`. WASM, that makes neither `_3nweb_mp1_write_msg_into`,
+/// nor `_3nweb_mp1_refuse_msg` call, as older modules do for refused
+/// messages, is reported as not taking message.
+///
+pub(crate) fn in_msg_outcome(ctx: &mut Mp1Ctx) -> Result<(), HostError> {
+	let is_refused = ctx.take_in_msg_refusal();
+	// message is dropped, if instance didn't ask for it
+	let is_left = ctx.take_in_msg().is_some();
+	if is_refused {
+		Err(refused_in_msg())
+	} else if is_left {
+		Err(HostError::new("WASM instance neither took, nor refused incoming message"))
+	} else {
+		Ok(())
+	}
+}
+
+/// Guesses versions of message passing, supported by WASM instance without
+/// `_3nweb_mp_versions`, from its exported functions, which `has_export`
+/// tells about.
+///
+pub(crate) fn versions_from_exports(
+	mut has_export: impl FnMut(&str) -> bool
+) -> MpVersions {
+	let mut versions = Vec::new();
+	if has_export("_3nweb_mp1_accept_msg") {
+		versions.push(MP1);
+	}
+	if has_export("_3nweb_mp2_rings") && has_export("_3nweb_mp2_doorbell") {
+		versions.push(MP2);
+	}
+	let capabilities = if has_export("_3nweb_mp1_get_buffer") {
+		Capabilities::GET_BUFFER
+	} else {
+		Capabilities::NONE
+	};
+	MpVersions { versions, max_msg_size: None, capabilities }
+}
+
+/// Reads versions descriptor at `ptr` in WASM's `memory`, which exported
+/// `_3nweb_mp_versions` has returned.
+///
+pub(crate) fn read_versions_descriptor(
+	memory: &[u8], ptr: u32
+) -> Result<MpVersions, HostError> {
+	let malformed = || HostError::new("Versions descriptor is malformed");
+	let ptr = ptr as usize;
+	let num_bytes = area(memory, ptr, 4).ok_or_else(malformed)?;
+	let num = MpVersions::num_of_versions(&[
+		num_bytes[0], num_bytes[1], num_bytes[2], num_bytes[3]
+	]).ok_or_else(malformed)?;
+	let descriptor = area(memory, ptr, MpVersions::descriptor_len(num))
+	.ok_or_else(malformed)?;
+	MpVersions::from_descriptor(descriptor).ok_or_else(malformed)
+}
+
+/// WASM instance with its store, into which embedder sends messages with
+/// engine's `send_into`. Engine modules implement this for their
+/// `Mp1Instance`.
+///
+pub trait Mp1Sender {
+
+	/// Engine's error.
+	type Error: fmt::Display;
+
+	fn send_msg(&mut self, msg: Vec<u8>) -> Result<(), Self::Error>;
+
+}
+
+/// Transport of calls, made by clients that `mp1_service` generates, into
+/// service in WASM instance, which serves them with `service::serve`.
+///
+/// Each call sends rpc request frame into instance with `sender`. Its reply
+/// is picked among instance's messages by [`HostCalls`], which should make
+/// [`Mp1Ctx`] in instance's store with [`HostCalls::mp1_ctx`]. Service, that
+/// replies synchronously, completes call within sending, while later replies
+/// come during later calls into instance.
+///
+#[cfg(feature = "service")]
+pub struct ServiceTransport<I> {
+	sender: I,
+	calls: HostCalls,
+}
+
+#[cfg(feature = "service")]
+impl<I> ServiceTransport<I> {
+
+	pub fn new(sender: I, calls: HostCalls) -> Self {
+		ServiceTransport { sender, calls }
+	}
+
+	pub fn sender(&mut self) -> &mut I {
+		&mut self.sender
+	}
+
+}
+
+#[cfg(feature = "service")]
+impl<I: Mp1Sender> Transport for ServiceTransport<I> {
+	fn call(
+		&mut self, request: Vec<u8>
+	) -> impl Future<Output = Result<Vec<u8>, ServiceError>> {
+		let call_id = self.calls.start_call();
+		let frame = Frame { kind: FrameKind::Request, call_id, body: request };
+		if let Err(err) = self.sender.send_msg(frame.encode()) {
+			self.calls.fail_call(call_id, ServiceError::Transport(err.to_string()));
+		}
+		self.calls.reply(call_id)
+	}
+}
+
+/// Instance of reference guest of `conformance` module, driven by
+/// conformance harness through engine's embedding. Engine modules start it
+/// with `ConformanceHost::start`.
+///
+/// Instance's [`Mp1Ctx`] should give messages from instance to `out_msgs`
+/// channel, as context from [`Mp1Ctx::with_channel`] does.
+///
+#[cfg(feature = "conformance")]
+pub struct ConformanceHost<I> {
+	sender: I,
+	out_msgs: mpsc::Receiver<Vec<u8>>,
+}
+
+#[cfg(feature = "conformance")]
+impl<I> ConformanceHost<I> {
+	pub(crate) fn new(sender: I, out_msgs: mpsc::Receiver<Vec<u8>>) -> Self {
+		ConformanceHost { sender, out_msgs }
+	}
+}
+
+#[cfg(feature = "conformance")]
+impl<I: Mp1Sender> HostUnderTest for ConformanceHost<I> {
+	type Error = I::Error;
+
+	fn send(&mut self, msg: Vec<u8>) -> Result<(), Self::Error> {
+		self.sender.send_msg(msg)
+	}
+
+	fn take_out_msgs(&mut self) -> Vec<Vec<u8>> {
+		self.out_msgs.try_iter().collect()
+	}
+}
+
+/// Embedder's state of message passing, version 2, with a particular WASM
+/// instance.
+///
+#[cfg(feature = "mp2")]
+pub struct Mp2Ctx {
+	rings: Option<Mp2Rings>,
+	out_msg_handler: OutMsgHandler,
+}
+
+#[cfg(feature = "mp2")]
+impl Mp2Ctx {
+
+	/// Creates context that gives messages from WASM instance to given
+	/// `out_msg_handler`.
+	///
+	pub fn new(out_msg_handler: impl FnMut(Vec<u8>) + Send + 'static) -> Self {
+		Mp2Ctx {
+			rings: None,
+			out_msg_handler: Box::new(out_msg_handler),
+		}
+	}
+
+	/// Creates context that puts messages from WASM instance into a channel,
+	/// returning receiving end of it.
+	///
+	pub fn with_channel() -> (Self, mpsc::Receiver<Vec<u8>>) {
+		let (sender, receiver) = mpsc::channel();
+		let ctx = Mp2Ctx::new(move |msg| {
+			// receiver may be gone, and then nobody cares about the message
+			let _ = sender.send(msg);
+		});
+		(ctx, receiver)
+	}
+
+	pub(crate) fn rings(&self) -> Option<Mp2Rings> {
+		self.rings
+	}
+
+	pub(crate) fn set_rings(&mut self, rings: Mp2Rings) {
+		self.rings = Some(rings);
+	}
+
+	pub(crate) fn deliver_out_msg(&mut self, msg: Vec<u8>) {
+		(self.out_msg_handler)(msg);
+	}
+
+}
+
+/// Gives access to [`Mp2Ctx`] inside of engine's store data.
+///
+#[cfg(feature = "mp2")]
+pub trait Mp2View {
+	fn mp2_ctx(&mut self) -> &mut Mp2Ctx;
+}
+
+#[cfg(feature = "mp2")]
+impl Mp2View for Mp2Ctx {
+	fn mp2_ctx(&mut self) -> &mut Mp2Ctx {
+		self
+	}
+}
+
+/// Location of rings in WASM's memory, read from descriptor that exported
+/// `_3nweb_mp2_rings` points to.
+///
+#[cfg(feature = "mp2")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Mp2Rings {
+	in_area: (usize, usize),
+	out_area: (usize, usize),
+}
+
+#[cfg(feature = "mp2")]
+impl Mp2Rings {
+
+	const DESCRIPTOR_LEN: usize = 16;
+
+	/// Reads location of rings from descriptor at `ptr` in WASM's `memory`,
+	/// which exported `_3nweb_mp2_rings` has returned.
+	///
+	pub(crate) fn read(memory: &[u8], ptr: u32) -> Result<Self, HostError> {
+		let bytes = area(memory, ptr as usize, Self::DESCRIPTOR_LEN)
+		.ok_or_else(|| HostError::new("Rings descriptor is out of memory bounds"))?;
+		let field = |i: usize| read_u32(bytes, i) as usize;
+		Ok(Mp2Rings {
+			in_area: (field(0), field(4)),
+			out_area: (field(8), field(12)),
+		})
+	}
+
+	fn ring_in(memory: &mut [u8], (ptr, len): (usize, usize)) -> Option<Ring<'_>> {
+		let area = memory.get_mut(ptr..ptr.checked_add(len)?)?;
+		Ring::new(area)
+	}
+
+	/// Returns view of inbound ring in given instance's memory, or `None`, if
+	/// ring is out of memory bounds, or is malformed.
+	///
+	fn in_ring<'a>(&self, memory: &'a mut [u8]) -> Option<Ring<'a>> {
+		Self::ring_in(memory, self.in_area)
+	}
+
+	/// Returns view of outbound ring in given instance's memory, or `None`, if
+	/// ring is out of memory bounds, or is malformed.
+	///
+	fn out_ring<'a>(&self, memory: &'a mut [u8]) -> Option<Ring<'a>> {
+		Self::ring_in(memory, self.out_area)
+	}
+
+	/// Pushes message into inbound ring in WASM's `memory`. Outer error is for
+	/// malformed ring.
+	///
+	pub(crate) fn push_in_msg(
+		&self, memory: &mut [u8], msg: &[u8]
+	) -> Result<Result<(), PushError>, HostError> {
+		self.in_ring(memory)
+		.map(|mut ring| ring.push(msg))
+		.ok_or_else(|| HostError::new("Inbound ring is malformed"))
+	}
+
+	/// Gives messages, popped from outbound ring in WASM's `memory`, to `ctx`.
+	///
+	pub(crate) fn take_out_msgs(
+		&self, memory: &mut [u8], ctx: &mut Mp2Ctx
+	) -> Result<(), HostError> {
+		let mut ring = self.out_ring(memory)
+		.ok_or_else(|| HostError::new("Outbound ring is malformed"))?;
+		while let Some(msg) = ring.pop() {
+			ctx.deliver_out_msg(msg);
+		}
+		Ok(())
+	}
+
+}
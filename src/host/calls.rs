@@ -0,0 +1,187 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Embedder's calls into service in WASM instance, made by clients that
+//! `mp1_service` generates.
+//!
+//! Calls are rpc request frames, sent into instance by
+//! [`ServiceTransport`](super::ServiceTransport), and replies are rpc frames from instance, which
+//! [`HostCalls`] picks among instance's messages, before they get to handler
+//! in [`Mp1Ctx`]. Call's future resolves, when its reply comes, which happens
+//! within engine's calls into instance.
+//!
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use crate::rpc::{FrameKind, HEADER_LEN};
+use crate::service::ServiceError;
+use super::Mp1Ctx;
+
+enum PendingCall {
+	Waiting(Option<Waker>),
+	Done(Result<Vec<u8>, ServiceError>),
+}
+
+#[derive(Default)]
+struct Calls {
+	next_call_id: u32,
+	pending: HashMap<u32, PendingCall>,
+}
+
+/// Pending calls of embedder into service in WASM instance. Clones share the
+/// same calls.
+///
+#[derive(Clone, Default)]
+pub struct HostCalls {
+	calls: Arc<Mutex<Calls>>,
+}
+
+impl HostCalls {
+
+	pub fn new() -> Self {
+		HostCalls::default()
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Calls> {
+		// calls stay consistent, even if other thread has panicked
+		self.calls.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+
+	/// Creates context that takes replies to these calls, giving all other
+	/// messages from WASM instance to `out_msg_handler`.
+	///
+	pub fn mp1_ctx(
+		&self, mut out_msg_handler: impl FnMut(Vec<u8>) + Send + 'static
+	) -> Mp1Ctx {
+		let calls = self.clone();
+		Mp1Ctx::new(move |msg| {
+			if let Some(msg) = calls.take_reply(msg) {
+				out_msg_handler(msg);
+			}
+		})
+	}
+
+	/// Takes message from WASM instance, if it is reply or error frame of a
+	/// pending call, returning other messages back.
+	///
+	pub fn take_reply(&self, mut msg: Vec<u8>) -> Option<Vec<u8>> {
+		if msg.len() < HEADER_LEN {
+			return Some(msg);
+		}
+		let is_error = match msg[0] {
+			kind if kind == FrameKind::Reply as u8 => false,
+			kind if kind == FrameKind::Error as u8 => true,
+			_ => return Some(msg),
+		};
+		let call_id = u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]]);
+		let waker = {
+			let mut calls = self.lock();
+			let Some(PendingCall::Waiting(waker)) = calls.pending.get_mut(&call_id) else {
+				return Some(msg);
+			};
+			let waker = waker.take();
+			msg.drain(..HEADER_LEN);
+			let result = if is_error {
+				Err(ServiceError::from_error_body(msg))
+			} else {
+				Ok(msg)
+			};
+			calls.pending.insert(call_id, PendingCall::Done(result));
+			waker
+		};
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+		None
+	}
+
+	/// Registers new call, returning its id.
+	///
+	pub(crate) fn start_call(&self) -> u32 {
+		let mut calls = self.lock();
+		loop {
+			let call_id = calls.next_call_id;
+			calls.next_call_id = calls.next_call_id.wrapping_add(1);
+			if let Entry::Vacant(entry) = calls.pending.entry(call_id) {
+				entry.insert(PendingCall::Waiting(None));
+				return call_id;
+			}
+		}
+	}
+
+	/// Completes call with an error, if it is still waiting for reply.
+	///
+	pub(crate) fn fail_call(&self, call_id: u32, err: ServiceError) {
+		let waker = {
+			let mut calls = self.lock();
+			match calls.pending.get_mut(&call_id) {
+				Some(PendingCall::Waiting(waker)) => {
+					let waker = waker.take();
+					calls.pending.insert(call_id, PendingCall::Done(Err(err)));
+					waker
+				},
+				_ => None,
+			}
+		};
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+
+	/// Returns future of reply to call, started with [`Self::start_call`].
+	///
+	pub(crate) fn reply(&self, call_id: u32) -> HostCall {
+		HostCall { call_id, calls: self.clone() }
+	}
+
+}
+
+/// Future of reply to embedder's call into WASM instance.
+///
+/// Dropping it forgets the call, and its late reply is given to handler of
+/// other messages.
+///
+pub struct HostCall {
+	call_id: u32,
+	calls: HostCalls,
+}
+
+impl Future for HostCall {
+	type Output = Result<Vec<u8>, ServiceError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut calls = self.calls.lock();
+		match calls.pending.remove(&self.call_id) {
+			Some(PendingCall::Done(result)) => Poll::Ready(result),
+			Some(PendingCall::Waiting(_)) => {
+				calls.pending.insert(
+					self.call_id, PendingCall::Waiting(Some(cx.waker().clone()))
+				);
+				Poll::Pending
+			},
+			None => Poll::Ready(Err(ServiceError::Closed)),
+		}
+	}
+}
+
+impl Drop for HostCall {
+	fn drop(&mut self) {
+		self.calls.lock().pending.remove(&self.call_id);
+	}
+}
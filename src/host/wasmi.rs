@@ -0,0 +1,352 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Embedding side of message passing, version 1, for `wasmi` interpreter.
+//!
+//! Message passing, version 1, is used as following:
+//! - add imports to linker with [`add_to_linker`],
+//! - instantiate WASM module with this linker,
+//! - send messages into instance with [`send_into`], while messages from
+//!   instance are given to handler in [`Mp1Ctx`](super::Mp1Ctx).
+//!
+//! Versions, supported by instance, are read with [`read_versions`], so that
+//! embedder can pick one before sending messages.
+//!
+//! Instance with its store is [`Mp1Instance`], which sends messages for
+//! generic parts of `host`. With `service` feature, `ServiceTransport` lets
+//! typed service clients call service in instance over message passing,
+//! version 1. With `conformance` feature, `ConformanceHost` runs conformance
+//! harness against this embedding and reference guest.
+//!
+//! With `mp2` feature, message passing, version 2, is used in the same way
+//! with `add_mp2_to_linker` and `send_mp2_msgs_into`, while messages from
+//! instance are given to handler in `Mp2Ctx`.
+//!
+
+use ::wasmi::{
+	AsContext, AsContextMut, Caller, Error, Extern, Instance, Linker, Memory
+};
+use crate::mp_versions::MpVersions;
+use super::{HostError, Mp1Sender, Mp1View};
+
+#[cfg(feature = "mp2")]
+use ::wasmi::TypedFunc;
+#[cfg(feature = "mp2")]
+use crate::wasm_mp2::ring::PushError;
+#[cfg(feature = "mp2")]
+use super::{Mp2Rings, Mp2View};
+#[cfg(feature = "conformance")]
+use std::sync::mpsc::Receiver;
+#[cfg(feature = "conformance")]
+use crate::conformance::GUEST_START_EXPORT;
+
+fn engine_err(err: HostError) -> Error {
+	Error::new(err.to_string())
+}
+
+fn memory_of<T>(caller: &Caller<'_, T>) -> Result<Memory, Error> {
+	match caller.get_export("memory") {
+		Some(Extern::Memory(memory)) => Ok(memory),
+		_ => Err(Error::new("WASM instance doesn't export memory")),
+	}
+}
+
+fn exported_memory(
+	store: impl AsContext, instance: &Instance
+) -> Result<Memory, Error> {
+	instance.get_memory(store, "memory")
+	.ok_or_else(|| Error::new("WASM instance doesn't export memory"))
+}
+
+/// Adds to `linker` functions `_3nweb_mp1_send_out_msg`,
+/// `_3nweb_mp1_try_send_out_msg`, `_3nweb_mp1_send_out_msg_parts`,
+/// `_3nweb_mp1_write_msg_into`, `_3nweb_mp1_refuse_msg` and `_3nweb_mp1_panic`
+/// in `env` namespace, as expected by WASM that uses message passing,
+/// version 1.
+///
+/// Panic report from `_3nweb_mp1_panic` is kept in [`Mp1Ctx`](super::Mp1Ctx),
+/// while malformed reports are ignored, as WASM traps right after them.
+///
+pub fn add_to_linker<T: Mp1View + 'static>(linker: &mut Linker<T>) -> Result<(), Error> {
+
+	linker.func_wrap(
+		"env", "_3nweb_mp1_send_out_msg",
+		|mut caller: Caller<'_, T>, ptr: u32, len: u32| -> Result<(), Error> {
+			let memory = memory_of(&caller)?;
+			let (data, state) = memory.data_and_store_mut(&mut caller);
+			// legacy import has no status, and rejected message is dropped
+			super::take_out_msg(data, state.mp1_ctx(), ptr, len)
+			.map(|_| ()).map_err(engine_err)
+		}
+	)?;
+
+	linker.func_wrap(
+		"env", "_3nweb_mp1_try_send_out_msg",
+		|mut caller: Caller<'_, T>, ptr: u32, len: u32| -> Result<u32, Error> {
+			let memory = memory_of(&caller)?;
+			let (data, state) = memory.data_and_store_mut(&mut caller);
+			super::take_out_msg(data, state.mp1_ctx(), ptr, len)
+			.map(super::send_status).map_err(engine_err)
+		}
+	)?;
+
+	linker.func_wrap(
+		"env", "_3nweb_mp1_send_out_msg_parts",
+		|mut caller: Caller<'_, T>, parts_ptr: u32, num_of_parts: u32| -> Result<u32, Error> {
+			let memory = memory_of(&caller)?;
+			let (data, state) = memory.data_and_store_mut(&mut caller);
+			super::take_out_msg_parts(data, state.mp1_ctx(), parts_ptr, num_of_parts)
+			.map(super::send_status).map_err(engine_err)
+		}
+	)?;
+
+	linker.func_wrap(
+		"env", "_3nweb_mp1_write_msg_into",
+		|mut caller: Caller<'_, T>, ptr: u32| -> Result<(), Error> {
+			let memory = memory_of(&caller)?;
+			let (data, state) = memory.data_and_store_mut(&mut caller);
+			super::write_in_msg(data, state.mp1_ctx(), ptr).map_err(engine_err)
+		}
+	)?;
+
+	linker.func_wrap(
+		"env", "_3nweb_mp1_refuse_msg",
+		|mut caller: Caller<'_, T>, _len: u32| -> Result<(), Error> {
+			caller.data_mut().mp1_ctx().refuse_in_msg();
+			Ok(())
+		}
+	)?;
+
+	linker.func_wrap(
+		"env", "_3nweb_mp1_panic",
+		|mut caller: Caller<'_, T>, ptr: u32, len: u32| -> Result<(), Error> {
+			let memory = memory_of(&caller)?;
+			let (data, state) = memory.data_and_store_mut(&mut caller);
+			super::keep_panic_report(data, state.mp1_ctx(), ptr, len);
+			Ok(())
+		}
+	)?;
+
+	Ok(())
+}
+
+/// Reads versions of message passing, supported by WASM `instance`, from
+/// descriptor that exported `_3nweb_mp_versions` points to.
+///
+/// For instance without `_3nweb_mp_versions`, versions and capabilities are
+/// guessed from its exports.
+///
+pub fn read_versions<T: 'static>(
+	mut store: impl AsContextMut<Data = T>, instance: &Instance
+) -> Result<MpVersions, Error> {
+	if instance.get_func(&store, "_3nweb_mp_versions").is_none() {
+		return Ok(super::versions_from_exports(
+			|name| instance.get_func(&store, name).is_some()
+		));
+	}
+	let versions_fn = instance.get_typed_func::<(), u32>(
+		&store, "_3nweb_mp_versions"
+	)?;
+	let memory = exported_memory(&store, instance)?;
+	let ptr = versions_fn.call(&mut store, ())?;
+	super::read_versions_descriptor(memory.data(&store), ptr).map_err(engine_err)
+}
+
+/// Sends given message into WASM `instance` by calling its exported
+/// `_3nweb_mp1_accept_msg`.
+///
+/// When instance exports `_3nweb_mp1_get_buffer`, message is copied into
+/// memory area that it returns, before `_3nweb_mp1_accept_msg` call.
+/// Otherwise, message bytes are written into instance's memory when it calls
+/// back `_3nweb_mp1_write_msg_into`.
+///
+/// Error is returned, when instance refuses message for being above its
+/// maximum size, which is seen as zero pointer from `_3nweb_mp1_get_buffer`,
+/// or as `_3nweb_mp1_refuse_msg` call. Instance, that makes neither
+/// `_3nweb_mp1_write_msg_into`, nor `_3nweb_mp1_refuse_msg` call, as older
+/// modules do for refused messages, is reported as not taking message.
+///
+pub fn send_into<T: Mp1View + 'static>(
+	mut store: impl AsContextMut<Data = T>, instance: &Instance, msg: Vec<u8>
+) -> Result<(), Error> {
+	let accept_msg = instance.get_typed_func::<u32, ()>(
+		&store, "_3nweb_mp1_accept_msg"
+	)?;
+	let len = super::in_msg_len(&msg).map_err(engine_err)?;
+	if instance.get_func(&store, "_3nweb_mp1_get_buffer").is_some() {
+		let get_buffer = instance.get_typed_func::<u32, u32>(
+			&store, "_3nweb_mp1_get_buffer"
+		)?;
+		let memory = exported_memory(&store, instance)?;
+		let ptr = get_buffer.call(&mut store, len)?;
+		super::write_in_msg_into_buffer(memory.data_mut(&mut store), ptr, &msg)
+		.map_err(engine_err)?;
+		accept_msg.call(&mut store, len)
+	} else {
+		store.as_context_mut().data_mut().mp1_ctx().set_in_msg(msg);
+		let result = accept_msg.call(&mut store, len);
+		let outcome = super::in_msg_outcome(store.as_context_mut().data_mut().mp1_ctx());
+		result?;
+		outcome.map_err(engine_err)
+	}
+}
+
+/// WASM instance with its store, into which messages are sent with
+/// [`send_into`].
+///
+pub struct Mp1Instance<S> {
+	store: S,
+	instance: Instance,
+}
+
+impl<S> Mp1Instance<S> {
+
+	pub fn new(store: S, instance: Instance) -> Self {
+		Mp1Instance { store, instance }
+	}
+
+	pub fn store(&mut self) -> &mut S {
+		&mut self.store
+	}
+
+	pub fn instance(&self) -> &Instance {
+		&self.instance
+	}
+
+}
+
+impl<S, T> Mp1Sender for Mp1Instance<S>
+where
+	S: AsContextMut<Data = T>,
+	T: Mp1View + 'static
+{
+	type Error = Error;
+
+	fn send_msg(&mut self, msg: Vec<u8>) -> Result<(), Error> {
+		send_into(&mut self.store, &self.instance, msg)
+	}
+}
+
+/// Transport of service calls into [`Mp1Instance`].
+///
+#[cfg(feature = "service")]
+pub type ServiceTransport<S> = super::ServiceTransport<Mp1Instance<S>>;
+
+/// Reference guest in [`Mp1Instance`], driven by conformance harness.
+///
+#[cfg(feature = "conformance")]
+pub type ConformanceHost<S> = super::ConformanceHost<Mp1Instance<S>>;
+
+#[cfg(feature = "conformance")]
+impl<S, T> super::ConformanceHost<Mp1Instance<S>>
+where
+	S: AsContextMut<Data = T>,
+	T: Mp1View + 'static
+{
+
+	/// Starts reference guest by calling its exported
+	/// `_3nweb_conformance_start`.
+	///
+	pub fn start(
+		mut store: S, instance: Instance, out_msgs: Receiver<Vec<u8>>
+	) -> Result<Self, Error> {
+		let start = instance.get_typed_func::<(), ()>(
+			&store, GUEST_START_EXPORT
+		)?;
+		start.call(&mut store, ())?;
+		Ok(Self::new(Mp1Instance::new(store, instance), out_msgs))
+	}
+
+}
+
+/// Reads location of rings from descriptor, returned by `rings_fn`, which is
+/// instance's exported `_3nweb_mp2_rings`, and caches it in context.
+///
+#[cfg(feature = "mp2")]
+fn read_mp2_rings<T: Mp2View + 'static>(
+	mut store: impl AsContextMut<Data = T>,
+	rings_fn: TypedFunc<(), u32>, memory: Memory
+) -> Result<Mp2Rings, Error> {
+	let ptr = rings_fn.call(&mut store, ())?;
+	let rings = Mp2Rings::read(memory.data(&store), ptr).map_err(engine_err)?;
+	store.as_context_mut().data_mut().mp2_ctx().set_rings(rings);
+	Ok(rings)
+}
+
+/// Adds to `linker` function `_3nweb_mp2_ring_doorbell` in `env` namespace, as
+/// expected by WASM that uses message passing, version 2. During its call,
+/// messages are popped from instance's outbound ring and given to handler in
+/// [`Mp2Ctx`](super::Mp2Ctx).
+///
+#[cfg(feature = "mp2")]
+pub fn add_mp2_to_linker<T: Mp2View + 'static>(linker: &mut Linker<T>) -> Result<(), Error> {
+
+	linker.func_wrap(
+		"env", "_3nweb_mp2_ring_doorbell",
+		|mut caller: Caller<'_, T>| -> Result<(), Error> {
+			let memory = memory_of(&caller)?;
+			let rings = match caller.data_mut().mp2_ctx().rings() {
+				Some(rings) => rings,
+				None => {
+					let rings_fn = match caller.get_export("_3nweb_mp2_rings") {
+						Some(Extern::Func(func)) => func.typed::<(), u32>(&caller)?,
+						_ => return Err(Error::new("WASM instance doesn't export _3nweb_mp2_rings")),
+					};
+					read_mp2_rings(&mut caller, rings_fn, memory)?
+				}
+			};
+			let (data, state) = memory.data_and_store_mut(&mut caller);
+			rings.take_out_msgs(data, state.mp2_ctx()).map_err(engine_err)
+		}
+	)?;
+
+	Ok(())
+}
+
+/// Sends given messages into WASM `instance`, which uses message passing,
+/// version 2. Messages are pushed into instance's inbound ring, and exported
+/// `_3nweb_mp2_doorbell` is called when ring gets full, and after the last
+/// message.
+///
+#[cfg(feature = "mp2")]
+pub fn send_mp2_msgs_into<T: Mp2View + 'static>(
+	mut store: impl AsContextMut<Data = T>, instance: &Instance,
+	msgs: impl IntoIterator<Item = Vec<u8>>
+) -> Result<(), Error> {
+	let doorbell = instance.get_typed_func::<(), ()>(
+		&store, "_3nweb_mp2_doorbell"
+	)?;
+	let memory = exported_memory(&store, instance)?;
+	let rings = match store.as_context_mut().data_mut().mp2_ctx().rings() {
+		Some(rings) => rings,
+		None => {
+			let rings_fn = instance.get_typed_func::<(), u32>(
+				&store, "_3nweb_mp2_rings"
+			)?;
+			read_mp2_rings(&mut store, rings_fn, memory)?
+		}
+	};
+	for msg in msgs {
+		let mut pushed = rings.push_in_msg(memory.data_mut(&mut store), &msg)
+		.map_err(engine_err)?;
+		if pushed == Err(PushError::Full) {
+			doorbell.call(&mut store, ())?;
+			pushed = rings.push_in_msg(memory.data_mut(&mut store), &msg)
+			.map_err(engine_err)?;
+		}
+		pushed.map_err(|err| engine_err(err.into()))?;
+	}
+	doorbell.call(&mut store, ())
+}
@@ -13,7 +13,89 @@
 // You should have received a copy of the GNU Lesser General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+// Without `std` feature, only `wasm_mp1` (with `mp_versions`) is available,
+// and it needs only `alloc`.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// Exports need either wasm-bindgen, or plain C-ABI symbols.
+#[cfg(not(any(feature = "wasm-bindgen", feature = "raw-abi")))]
+compile_error!("either `wasm-bindgen` (default) or `raw-abi` feature should be enabled");
+
+#[macro_use]
+mod local;
+
 /// This module provide rust implementation for WASM module to talk with the
 /// outside according to version 1 of 3nweb's message passing api (should be
 /// called abi?).
 pub mod wasm_mp1;
+
+/// This module provide rust implementation for WASM module to talk with the
+/// outside according to version 2 of 3nweb's message passing api, with ring
+/// buffers in shared memory.
+#[cfg(feature = "mp2")]
+pub mod wasm_mp2;
+
+mod processor;
+
+/// This module describes versions of message passing, supported by WASM
+/// module, for embedder to pick one.
+pub mod mp_versions;
+
+/// This module provides request/response calls on top of message passing,
+/// version 1.
+#[cfg(feature = "std")]
+pub mod rpc;
+
+/// This module adds credit-based flow control to message passing, version 1.
+#[cfg(feature = "std")]
+pub mod flow;
+
+/// This module multiplexes channels with their own processors over message
+/// passing, version 1.
+#[cfg(feature = "std")]
+pub mod mux;
+
+/// This module reports panics of WASM code to embedder, with `panic-hook`
+/// feature, and decodes such reports on embedder's side.
+#[cfg(feature = "std")]
+pub mod panic_hook;
+
+/// This module forwards `log` and `tracing` records to embedder in a log
+/// channel, and decodes them on embedder's side.
+#[cfg(any(feature = "log", feature = "tracing"))]
+pub mod logging;
+
+/// This module provides messages from the outside as an async stream.
+#[cfg(feature = "stream")]
+pub mod msg_stream;
+
+/// This module streams large payloads in chunks over message passing,
+/// version 1.
+#[cfg(feature = "stream")]
+pub mod chunked;
+
+/// This module passes serde-typed messages, serialized with pluggable codecs.
+#[cfg(feature = "typed")]
+pub mod typed;
+
+/// This module runs services, declared with `mp1_service` attribute on a
+/// trait, over `rpc`.
+#[cfg(feature = "service")]
+pub mod service;
+
+/// This module checks embedders against reference guest, built from this
+/// crate, with conformance harness.
+#[cfg(feature = "conformance")]
+pub mod conformance;
+
+/// This module provides in-process mock of embedding, so that code using
+/// message passing can be tested natively with `cargo test`.
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+pub mod testing;
+
+/// This module provides embedder (host) side of 3nweb's message passing api,
+/// with engine specific parts enabled by cargo features.
+#[cfg(any(feature = "wasmtime", feature = "wasmi"))]
+pub mod host;
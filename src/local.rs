@@ -0,0 +1,80 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Per-thread state of message passing.
+//!
+//! With `std` feature, state is kept in `thread_local!` statics. Without it,
+//! this module provides `thread_local!` macro and [`LocalKey`] with the same
+//! `with` method, so that modules, usable under `no_std`, are written the same
+//! way. Without `std`, state is kept in plain statics, as WASM module without
+//! threads has only one thread, and other targets aren't supported.
+//!
+
+#[cfg(feature = "std")]
+pub use std::thread::LocalKey;
+
+/// Static state in single-threaded WASM, accessed like `std::thread::LocalKey`.
+///
+#[cfg(not(feature = "std"))]
+pub struct LocalKey<T: 'static> {
+	value: T,
+}
+
+// Plain statics are per-thread state only where there is just one thread.
+#[cfg(all(
+	not(feature = "std"),
+	not(all(target_arch = "wasm32", not(target_feature = "atomics")))
+))]
+compile_error!("without `std` feature, only wasm32 without `atomics` target feature is supported");
+
+// WASM without threads has only one thread, which is the only one to touch
+// these statics.
+#[cfg(all(
+	not(feature = "std"), target_arch = "wasm32", not(target_feature = "atomics")
+))]
+unsafe impl<T: 'static> Sync for LocalKey<T> {}
+
+#[cfg(not(feature = "std"))]
+impl<T: 'static> LocalKey<T> {
+
+	#[doc(hidden)]
+	pub const fn new(value: T) -> Self {
+		LocalKey { value }
+	}
+
+	pub fn with<F, R>(&'static self, f: F) -> R
+	where
+		F: FnOnce(&T) -> R
+	{
+		f(&self.value)
+	}
+
+}
+
+/// Declares statics of [`LocalKey`], taking the same syntax with `const`
+/// initializers as `std::thread_local!` does.
+///
+#[cfg(not(feature = "std"))]
+macro_rules! thread_local {
+	($(
+		$(#[$attr:meta])*
+		static $name:ident: $t:ty = const { $init:expr };
+	)*) => {
+		$(
+			$(#[$attr])*
+			static $name: $crate::local::LocalKey<$t> = $crate::local::LocalKey::new($init);
+		)*
+	};
+}
@@ -0,0 +1,369 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module forwards log records of WASM code to embedder over message
+//! passing, version 1, so that guest logging needs no JS console.
+//!
+//! Records are sent in a log channel, i.e. every message is prefixed with
+//! little-endian `u32` channel id, like messages of `mux`. The rest of message
+//! is [`LogRecord`], encoded as a byte of level, followed by target, message
+//! and key-value pairs, each string being little-endian `u32` length and UTF-8
+//! bytes.
+//!
+//! With `log` feature, [`Mp1Logger`] implements `log::Log`, and [`init_log`]
+//! sets it as global logger. With `tracing` feature, [`Mp1Layer`] is a layer
+//! of `tracing_subscriber`. Embedder takes records from messages with
+//! [`decode_msg`], and re-emits them into its own `log` or `tracing`.
+//!
+
+use std::fmt;
+use crate::mux;
+use crate::wasm_mp1::{self, SendError};
+
+/// Level of log record, numbered like levels of `log` crate.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+	Error = 1,
+	Warn = 2,
+	Info = 3,
+	Debug = 4,
+	Trace = 5,
+}
+
+impl LogLevel {
+
+	pub fn from_u8(level: u8) -> Option<Self> {
+		match level {
+			1 => Some(LogLevel::Error),
+			2 => Some(LogLevel::Warn),
+			3 => Some(LogLevel::Info),
+			4 => Some(LogLevel::Debug),
+			5 => Some(LogLevel::Trace),
+			_ => None,
+		}
+	}
+
+}
+
+/// Log record, as it is passed in a message of log channel.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+	pub level: LogLevel,
+	pub target: String,
+	pub message: String,
+	pub key_values: Vec<(String, String)>,
+}
+
+fn push_str(bytes: &mut Vec<u8>, s: &str) {
+	bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+	bytes.extend_from_slice(s.as_bytes());
+}
+
+fn read_str(bytes: &[u8], pos: &mut usize) -> Option<String> {
+	let len = bytes.get(*pos..(*pos + 4))
+	.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)?;
+	let start = *pos + 4;
+	let end = start.checked_add(len)?;
+	let s = String::from_utf8_lossy(bytes.get(start..end)?).into_owned();
+	*pos = end;
+	Some(s)
+}
+
+impl LogRecord {
+
+	/// Encodes record into bytes, which follow channel id in a message.
+	///
+	pub fn encode(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			9 + self.target.len() + self.message.len()
+		);
+		bytes.push(self.level as u8);
+		push_str(&mut bytes, &self.target);
+		push_str(&mut bytes, &self.message);
+		for (key, value) in self.key_values.iter() {
+			push_str(&mut bytes, key);
+			push_str(&mut bytes, value);
+		}
+		bytes
+	}
+
+	/// Decodes record from bytes, returning `None` for malformed ones. Invalid
+	/// UTF-8 is replaced, as record is for diagnostics.
+	///
+	pub fn decode(bytes: &[u8]) -> Option<Self> {
+		let level = LogLevel::from_u8(*bytes.first()?)?;
+		let mut pos = 1;
+		let target = read_str(bytes, &mut pos)?;
+		let message = read_str(bytes, &mut pos)?;
+		let mut key_values = Vec::new();
+		while pos < bytes.len() {
+			let key = read_str(bytes, &mut pos)?;
+			let value = read_str(bytes, &mut pos)?;
+			key_values.push((key, value));
+		}
+		Some(LogRecord { level, target, message, key_values })
+	}
+
+	/// Re-emits this record into embedder's `log` logger, keeping level and
+	/// target of the original record.
+	///
+	#[cfg(feature = "log")]
+	pub fn emit_to_log(&self) {
+		let level = log::Level::from(self.level);
+		if level > log::max_level() {
+			return;
+		}
+		let key_values = self.key_values.iter()
+		.map(|(key, value)| (key.as_str(), value.as_str()))
+		.collect::<Vec<_>>();
+		log::logger().log(&log::Record::builder()
+			.args(format_args!("{}", self.message))
+			.level(level)
+			.target(&self.target)
+			.key_values(&key_values)
+			.build());
+	}
+
+	/// Re-emits this record as an event of embedder's `tracing` subscriber.
+	/// Event's target can't be set at runtime, hence, original target and
+	/// key-value pairs are fields `guest_target` and `key_values` of event.
+	///
+	#[cfg(feature = "tracing")]
+	pub fn emit_to_tracing(&self) {
+		let target = &self.target;
+		let key_values = &self.key_values;
+		let message = &self.message;
+		match self.level {
+			LogLevel::Error => tracing::error!(
+				guest_target = %target, key_values = ?key_values, "{}", message
+			),
+			LogLevel::Warn => tracing::warn!(
+				guest_target = %target, key_values = ?key_values, "{}", message
+			),
+			LogLevel::Info => tracing::info!(
+				guest_target = %target, key_values = ?key_values, "{}", message
+			),
+			LogLevel::Debug => tracing::debug!(
+				guest_target = %target, key_values = ?key_values, "{}", message
+			),
+			LogLevel::Trace => tracing::trace!(
+				guest_target = %target, key_values = ?key_values, "{}", message
+			),
+		}
+	}
+
+}
+
+impl fmt::Display for LogRecord {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:?} {}: {}", self.level, self.target, self.message)?;
+		for (key, value) in self.key_values.iter() {
+			write!(f, " {}={}", key, value)?;
+		}
+		Ok(())
+	}
+}
+
+/// Sends record to the outside in log channel with given id.
+///
+pub fn send_record(channel_id: u32, record: &LogRecord) -> Result<(), SendError> {
+	wasm_mp1::try_send_parts(&[&channel_id.to_le_bytes(), &record.encode()])
+}
+
+/// Takes log record from a message, returning `None`, when message isn't in
+/// log channel with given id, or is malformed. Embedder uses this to pick log
+/// records among messages from WASM.
+///
+pub fn decode_msg(channel_id: u32, msg: &[u8]) -> Option<LogRecord> {
+	let header = msg.get(..mux::HEADER_LEN)?;
+	if header != channel_id.to_le_bytes() {
+		return None;
+	}
+	LogRecord::decode(&msg[mux::HEADER_LEN..])
+}
+
+#[cfg(feature = "log")]
+impl From<log::Level> for LogLevel {
+	fn from(level: log::Level) -> Self {
+		match level {
+			log::Level::Error => LogLevel::Error,
+			log::Level::Warn => LogLevel::Warn,
+			log::Level::Info => LogLevel::Info,
+			log::Level::Debug => LogLevel::Debug,
+			log::Level::Trace => LogLevel::Trace,
+		}
+	}
+}
+
+#[cfg(feature = "log")]
+impl From<LogLevel> for log::Level {
+	fn from(level: LogLevel) -> Self {
+		match level {
+			LogLevel::Error => log::Level::Error,
+			LogLevel::Warn => log::Level::Warn,
+			LogLevel::Info => log::Level::Info,
+			LogLevel::Debug => log::Level::Debug,
+			LogLevel::Trace => log::Level::Trace,
+		}
+	}
+}
+
+/// Logger that sends records to the outside in log channel.
+///
+#[cfg(feature = "log")]
+pub struct Mp1Logger {
+	channel_id: u32,
+}
+
+#[cfg(feature = "log")]
+impl Mp1Logger {
+
+	pub fn new(channel_id: u32) -> Self {
+		Mp1Logger { channel_id }
+	}
+
+}
+
+#[cfg(feature = "log")]
+struct KeyValuesCollector(Vec<(String, String)>);
+
+#[cfg(feature = "log")]
+impl<'kvs> log::kv::VisitSource<'kvs> for KeyValuesCollector {
+	fn visit_pair(
+		&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>
+	) -> Result<(), log::kv::Error> {
+		self.0.push((key.as_str().to_string(), value.to_string()));
+		Ok(())
+	}
+}
+
+#[cfg(feature = "log")]
+impl log::Log for Mp1Logger {
+
+	fn enabled(&self, _: &log::Metadata<'_>) -> bool {
+		true
+	}
+
+	fn log(&self, record: &log::Record<'_>) {
+		let mut key_values = KeyValuesCollector(Vec::new());
+		let _ = record.key_values().visit(&mut key_values);
+		let record = LogRecord {
+			level: record.level().into(),
+			target: record.target().to_string(),
+			message: record.args().to_string(),
+			key_values: key_values.0,
+		};
+		// there is nowhere to log rejection of a log record
+		let _ = send_record(self.channel_id, &record);
+	}
+
+	fn flush(&self) {}
+
+}
+
+/// Sets [`Mp1Logger`] with given log channel as global logger of `log`, and
+/// sets maximum level of records.
+///
+#[cfg(feature = "log")]
+pub fn init_log(
+	channel_id: u32, max_level: log::LevelFilter
+) -> Result<(), log::SetLoggerError> {
+	log::set_boxed_logger(Box::new(Mp1Logger::new(channel_id)))?;
+	log::set_max_level(max_level);
+	Ok(())
+}
+
+#[cfg(feature = "tracing")]
+impl From<&tracing::Level> for LogLevel {
+	fn from(level: &tracing::Level) -> Self {
+		match *level {
+			tracing::Level::ERROR => LogLevel::Error,
+			tracing::Level::WARN => LogLevel::Warn,
+			tracing::Level::INFO => LogLevel::Info,
+			tracing::Level::DEBUG => LogLevel::Debug,
+			tracing::Level::TRACE => LogLevel::Trace,
+		}
+	}
+}
+
+/// Layer of `tracing_subscriber` that sends events to the outside in log
+/// channel. Event's `message` field becomes record's message, and other
+/// fields become key-value pairs.
+///
+#[cfg(feature = "tracing")]
+pub struct Mp1Layer {
+	channel_id: u32,
+}
+
+#[cfg(feature = "tracing")]
+impl Mp1Layer {
+
+	pub fn new(channel_id: u32) -> Self {
+		Mp1Layer { channel_id }
+	}
+
+}
+
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct FieldsCollector {
+	message: String,
+	key_values: Vec<(String, String)>,
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::field::Visit for FieldsCollector {
+
+	fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+		if field.name() == "message" {
+			self.message = value.to_string();
+		} else {
+			self.key_values.push((field.name().to_string(), value.to_string()));
+		}
+	}
+
+	fn record_debug(
+		&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug
+	) {
+		if field.name() == "message" {
+			self.message = format!("{:?}", value);
+		} else {
+			self.key_values.push((field.name().to_string(), format!("{:?}", value)));
+		}
+	}
+
+}
+
+#[cfg(feature = "tracing")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Mp1Layer {
+	fn on_event(
+		&self, event: &tracing::Event<'_>,
+		_: tracing_subscriber::layer::Context<'_, S>
+	) {
+		let mut fields = FieldsCollector::default();
+		event.record(&mut fields);
+		let metadata = event.metadata();
+		let record = LogRecord {
+			level: metadata.level().into(),
+			target: metadata.target().to_string(),
+			message: fields.message,
+			key_values: fields.key_values,
+		};
+		// there is nowhere to log rejection of a log record
+		let _ = send_record(self.channel_id, &record);
+	}
+}
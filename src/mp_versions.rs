@@ -0,0 +1,285 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module describes versions of message passing, supported by WASM
+//! module, so that embedder can pick one at instantiation time, instead of
+//! probing exports by name.
+//!
+//! With `mp-versions` feature, which `mp2` feature enables, WASM exports
+//! function `_3nweb_mp_versions`, which returns pointer to a descriptor of
+//! little-endian `u32` values:
+//! - number of supported versions, followed by versions themselves,
+//! - maximum size of message that WASM accepts, with `0` for no limit,
+//! - bit flags of optional [`Capabilities`].
+//!
+//! Modules without `_3nweb_mp_versions` export support only version 1.
+//!
+
+use alloc::vec::Vec;
+use core::ops::BitOr;
+
+/// Message passing, version 1, implemented in `wasm_mp1`.
+///
+pub const MP1: u32 = 1;
+
+/// Message passing, version 2, implemented in `wasm_mp2`.
+///
+pub const MP2: u32 = 2;
+
+/// Bit flags of optional capabilities of message passing.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+
+	/// No optional capabilities.
+	///
+	pub const NONE: Capabilities = Capabilities(0);
+
+	/// Inbound messages of version 1 use `_3nweb_mp1_get_buffer` handshake.
+	///
+	pub const GET_BUFFER: Capabilities = Capabilities(1);
+
+	/// Outbound messages of version 1 use `_3nweb_mp1_try_send_out_msg`
+	/// import, which returns status.
+	///
+	pub const TRY_SEND: Capabilities = Capabilities(2);
+
+	/// Outbound messages of version 1 may come in parts through
+	/// `_3nweb_mp1_send_out_msg_parts` import.
+	///
+	pub const SEND_PARTS: Capabilities = Capabilities(4);
+
+	/// Panics are reported through `_3nweb_mp1_panic` import.
+	///
+	pub const PANIC_REPORT: Capabilities = Capabilities(8);
+
+	/// Inbound messages of version 1, that are refused without
+	/// `_3nweb_mp1_get_buffer` handshake, are signalled with
+	/// `_3nweb_mp1_refuse_msg` import.
+	///
+	pub const REFUSE_MSG: Capabilities = Capabilities(16);
+
+	pub fn from_bits(bits: u32) -> Self {
+		Capabilities(bits)
+	}
+
+	pub fn bits(self) -> u32 {
+		self.0
+	}
+
+	/// Tells if all flags of `other` are set in this.
+	///
+	pub fn contains(self, other: Capabilities) -> bool {
+		(self.0 & other.0) == other.0
+	}
+
+}
+
+impl BitOr for Capabilities {
+	type Output = Capabilities;
+
+	fn bitor(self, other: Capabilities) -> Capabilities {
+		Capabilities(self.0 | other.0)
+	}
+}
+
+/// Content of versions descriptor.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpVersions {
+	/// Supported versions of message passing.
+	pub versions: Vec<u32>,
+	/// Maximum size of message that WASM accepts, if there is a limit.
+	pub max_msg_size: Option<u32>,
+	/// Optional capabilities.
+	pub capabilities: Capabilities,
+}
+
+impl MpVersions {
+
+	/// Maximum number of versions in a descriptor. Descriptors with more are
+	/// considered malformed.
+	///
+	pub const MAX_VERSIONS: usize = 64;
+
+	/// Returns length of descriptor's bytes with given number of versions.
+	///
+	pub fn descriptor_len(num_of_versions: usize) -> usize {
+		4 * (num_of_versions + 3)
+	}
+
+	/// Tells if given `version` is supported.
+	///
+	pub fn supports(&self, version: u32) -> bool {
+		self.versions.contains(&version)
+	}
+
+	/// Picks the highest version, supported both by WASM and by embedder with
+	/// `own_versions`.
+	///
+	pub fn pick(&self, own_versions: &[u32]) -> Option<u32> {
+		self.versions.iter()
+		.filter(|v| own_versions.contains(v))
+		.max()
+		.copied()
+	}
+
+	/// Serializes this into descriptor's bytes.
+	///
+	pub fn to_descriptor(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			Self::descriptor_len(self.versions.len())
+		);
+		bytes.extend_from_slice(&(self.versions.len() as u32).to_le_bytes());
+		for version in self.versions.iter() {
+			bytes.extend_from_slice(&version.to_le_bytes());
+		}
+		bytes.extend_from_slice(&self.max_msg_size.unwrap_or(0).to_le_bytes());
+		bytes.extend_from_slice(&self.capabilities.bits().to_le_bytes());
+		bytes
+	}
+
+	/// Reads number of versions from the first four bytes of descriptor.
+	/// Returns `None`, if number is above [`Self::MAX_VERSIONS`].
+	///
+	pub fn num_of_versions(bytes: &[u8; 4]) -> Option<usize> {
+		let num = u32::from_le_bytes(*bytes) as usize;
+		if num <= Self::MAX_VERSIONS { Some(num) } else { None }
+	}
+
+	/// Parses descriptor's bytes. Returns `None` for malformed descriptor.
+	///
+	pub fn from_descriptor(bytes: &[u8]) -> Option<Self> {
+		let num = Self::num_of_versions(bytes.get(0..4)?.try_into().ok()?)?;
+		if bytes.len() != Self::descriptor_len(num) {
+			return None;
+		}
+		let fields: Vec<u32> = bytes.chunks_exact(4)
+		.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+		.collect();
+		let versions = fields[1..(1 + num)].to_vec();
+		let max_msg_size = fields[1 + num];
+		let capabilities = Capabilities::from_bits(fields[2 + num]);
+		Some(MpVersions {
+			versions,
+			max_msg_size: if max_msg_size == 0 { None } else { Some(max_msg_size) },
+			capabilities,
+		})
+	}
+
+}
+
+pub(crate) mod internals {
+
+	#[cfg(all(feature = "mp-versions", not(feature = "raw-abi")))]
+	use wasm_bindgen::prelude::*;
+	use core::cell::Cell;
+	#[cfg(feature = "mp-versions")]
+	use alloc::vec::Vec;
+	#[cfg(feature = "mp-versions")]
+	use core::cell::RefCell;
+	use super::{Capabilities, MP1, MpVersions};
+	#[cfg(feature = "mp2")]
+	use super::MP2;
+
+	thread_local! {
+		static MAX_MSG_SIZE: Cell<Option<u32>> = const { Cell::new(None) };
+		#[cfg(feature = "mp-versions")]
+		static DESCRIPTOR: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+	}
+
+	/// Sets maximum size of message, advertised to embedder, and enforced by
+	/// `wasm_mp1`. This is implementation.
+	///
+	pub fn set_max_msg_size(max_msg_size: Option<u32>) {
+		MAX_MSG_SIZE.with(|cell| cell.set(max_msg_size));
+	}
+
+	pub fn max_msg_size() -> Option<u32> {
+		MAX_MSG_SIZE.with(Cell::get)
+	}
+
+	/// Returns versions, supported by this module. This is implementation.
+	///
+	pub fn supported_versions() -> MpVersions {
+		let mut capabilities = Capabilities::NONE;
+		if cfg!(feature = "get-buffer") {
+			capabilities = capabilities | Capabilities::GET_BUFFER;
+		}
+		if cfg!(feature = "try-send") {
+			capabilities = capabilities | Capabilities::TRY_SEND;
+		}
+		if cfg!(feature = "send-parts") {
+			capabilities = capabilities | Capabilities::SEND_PARTS;
+		}
+		if cfg!(feature = "panic-hook") {
+			capabilities = capabilities | Capabilities::PANIC_REPORT;
+		}
+		if cfg!(all(feature = "refuse-msg", not(feature = "get-buffer"))) {
+			capabilities = capabilities | Capabilities::REFUSE_MSG;
+		}
+		#[cfg(feature = "mp2")]
+		let versions = alloc::vec![ MP1, MP2 ];
+		#[cfg(not(feature = "mp2"))]
+		let versions = alloc::vec![ MP1 ];
+		MpVersions {
+			versions,
+			max_msg_size: max_msg_size(),
+			capabilities,
+		}
+	}
+
+	/// Don't use this directly.
+	/// This function is exported from WASM in accordance with 3nweb's message
+	/// passing api, indicated be `_3nweb_mp_` prefix in the name.
+	///
+	/// This is called by WASM embedding to learn supported versions of message
+	/// passing. Returned pointer points to descriptor, which stays intact till
+	/// the next call of this function.
+	///
+	#[cfg(feature = "mp-versions")]
+	#[cfg_attr(not(feature = "raw-abi"), wasm_bindgen)]
+	#[cfg_attr(feature = "raw-abi", no_mangle)]
+	pub extern "C-unwind" fn _3nweb_mp_versions() -> usize {
+		DESCRIPTOR.with(|descriptor| {
+			let mut descriptor = descriptor.borrow_mut();
+			*descriptor = supported_versions().to_descriptor();
+			descriptor.as_ptr() as usize
+		})
+	}
+
+}
+
+/// Sets maximum size of message that this module accepts, advertising it to
+/// embedder in versions descriptor, when it is exported. `None` means no
+/// limit, which is default.
+///
+/// This is the same limit as `wasm_mp1::set_max_in_msg_size` sets, and longer
+/// messages are refused by `wasm_mp1`.
+///
+#[inline]
+pub fn set_max_msg_size(max_msg_size: Option<u32>) {
+	internals::set_max_msg_size(max_msg_size);
+}
+
+/// Returns versions of message passing, supported by this module, as they are
+/// advertised to embedder with `mp-versions` feature.
+///
+#[inline]
+pub fn supported_versions() -> MpVersions {
+	internals::supported_versions()
+}
@@ -0,0 +1,178 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module provides messages from the outside as an async stream, as an
+//! alternative to a synchronous message processor.
+//!
+//! [`incoming_msgs`] sets in `wasm_mp1` a processor that puts messages into a
+//! bounded queue and wakes stream's consumer. Stream is usually consumed in a
+//! task, spawned with `wasm_bindgen_futures::spawn_local`.
+//!
+//! When queue is full, a message is dropped in accordance with [`Overflow`]
+//! policy, and dropped messages are counted. When stream's processor is
+//! replaced or taken from `wasm_mp1`, stream ends after queued messages.
+//! Dropping stream takes its processor from `wasm_mp1`, if it is still there.
+//!
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use crate::wasm_mp1;
+
+/// What to do with a message that comes when queue is full.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+	/// Incoming message is dropped, keeping already queued ones.
+	DropNewest,
+	/// The oldest queued message is dropped to make place for incoming one.
+	DropOldest,
+}
+
+struct Queue {
+	msgs: VecDeque<Vec<u8>>,
+	capacity: usize,
+	overflow: Overflow,
+	dropped: u64,
+	waker: Option<Waker>,
+	is_closed: bool,
+}
+
+impl Queue {
+
+	fn push(&mut self, msg: Vec<u8>) -> Option<Waker> {
+		if self.msgs.len() >= self.capacity {
+			self.dropped += 1;
+			match self.overflow {
+				Overflow::DropNewest => return None,
+				Overflow::DropOldest => {
+					self.msgs.pop_front();
+				},
+			}
+		}
+		self.msgs.push_back(msg);
+		self.waker.take()
+	}
+
+	fn close(&mut self) -> Option<Waker> {
+		self.is_closed = true;
+		self.waker.take()
+	}
+
+}
+
+/// Part of processor that closes queue, when processor is dropped.
+///
+struct QueueFeeder {
+	queue: Rc<RefCell<Queue>>,
+}
+
+impl QueueFeeder {
+	fn feed(&self, msg: Vec<u8>) {
+		let waker = self.queue.borrow_mut().push(msg);
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+}
+
+impl Drop for QueueFeeder {
+	fn drop(&mut self) {
+		let waker = self.queue.borrow_mut().close();
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+}
+
+/// Sets in `wasm_mp1` a processor that queues messages for returned stream,
+/// replacing whatever processor was there before.
+///
+/// Queue holds at most `capacity` messages (at least one), and `overflow`
+/// tells which message is dropped when queue is full.
+///
+pub fn incoming_msgs(capacity: usize, overflow: Overflow) -> IncomingMsgs {
+	let queue = Rc::new(RefCell::new(Queue {
+		msgs: VecDeque::new(),
+		capacity: capacity.max(1),
+		overflow,
+		dropped: 0,
+		waker: None,
+		is_closed: false,
+	}));
+	let feeder = QueueFeeder { queue: queue.clone() };
+	wasm_mp1::set_msg_processor(move |msg| feeder.feed(msg));
+	IncomingMsgs { queue }
+}
+
+/// Stream of messages from the outside, created by [`incoming_msgs`].
+///
+pub struct IncomingMsgs {
+	queue: Rc<RefCell<Queue>>,
+}
+
+impl IncomingMsgs {
+
+	/// Returns number of messages that have been dropped due to queue overflow.
+	///
+	pub fn dropped_count(&self) -> u64 {
+		self.queue.borrow().dropped
+	}
+
+	/// Returns number of messages, waiting in the queue.
+	///
+	pub fn queued_count(&self) -> usize {
+		self.queue.borrow().msgs.len()
+	}
+
+}
+
+impl Stream for IncomingMsgs {
+	type Item = Vec<u8>;
+
+	fn poll_next(
+		self: Pin<&mut Self>, cx: &mut Context<'_>
+	) -> Poll<Option<Self::Item>> {
+		let mut queue = self.queue.borrow_mut();
+		if let Some(msg) = queue.msgs.pop_front() {
+			Poll::Ready(Some(msg))
+		} else if queue.is_closed {
+			Poll::Ready(None)
+		} else {
+			queue.waker = Some(cx.waker().clone());
+			Poll::Pending
+		}
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		let queue = self.queue.borrow();
+		let len = queue.msgs.len();
+		(len, if queue.is_closed { Some(len) } else { None })
+	}
+}
+
+impl Drop for IncomingMsgs {
+	fn drop(&mut self) {
+		// open queue means that its feeder is still set in wasm_mp1
+		let is_fed = !self.queue.borrow().is_closed;
+		if is_fed {
+			// feeder is dropped outside of borrow, as it closes queue
+			wasm_mp1::take_msg_processor();
+		}
+	}
+}
@@ -0,0 +1,228 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module multiplexes independent channels over message passing,
+//! version 1, so that different parts of code have their own processors.
+//!
+//! Every message is prefixed with channel id as little-endian `u32`, and the
+//! rest of message is a body. Channel with a processor is opened by
+//! [`open_channel`], and inbound messages are routed to processors of their
+//! channels, after [`install`] has set this module's processor in `wasm_mp1`.
+//! Messages for channels that aren't open, and messages that are too short to
+//! have channel id, are given to a fallback, set by [`set_fallback`].
+//!
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use crate::wasm_mp1::{self, SendError};
+
+/// Length of channel id prefix.
+///
+pub const HEADER_LEN: usize = 4;
+
+/// Processor of a channel's messages, which get here without channel id.
+///
+pub type ChannelProcessor = Box<dyn FnMut(Vec<u8>)>;
+
+/// Message from the outside that has no channel to go to.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unrouted {
+	/// Message for a channel that isn't open, given without channel id.
+	UnknownChannel { channel_id: u32, body: Vec<u8> },
+	/// Message that is too short to have channel id.
+	Malformed(Vec<u8>),
+}
+
+/// Handler of messages that have no channel to go to.
+///
+pub type Fallback = Box<dyn FnMut(Unrouted)>;
+
+/// Error of opening a channel with id of already open channel.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelInUse(pub u32);
+
+impl fmt::Display for ChannelInUse {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "channel {} is already open", self.0)
+	}
+}
+
+impl std::error::Error for ChannelInUse {}
+
+struct Mux {
+	/// Processors of open channels. Processor is taken out for the duration
+	/// of its call.
+	channels: HashMap<u32, Option<ChannelProcessor>>,
+	fallback: Option<Fallback>,
+}
+
+thread_local! {
+	static MUX: RefCell<Mux> = RefCell::new(Mux {
+		channels: HashMap::new(),
+		fallback: None,
+	});
+}
+
+/// Prefixes `body` with `channel_id`, making a message. Embedder may use this
+/// for its side of channels.
+///
+pub fn encode(channel_id: u32, body: &[u8]) -> Vec<u8> {
+	let mut msg = Vec::with_capacity(HEADER_LEN + body.len());
+	msg.extend_from_slice(&channel_id.to_le_bytes());
+	msg.extend_from_slice(body);
+	msg
+}
+
+/// Splits message into channel id and body, returning `None` for message that
+/// is too short to have channel id.
+///
+pub fn decode(msg: Vec<u8>) -> Option<(u32, Vec<u8>)> {
+	split_channel_id(msg).ok()
+}
+
+/// Splits message like [`decode`] does, giving back message that is too short.
+///
+fn split_channel_id(mut msg: Vec<u8>) -> Result<(u32, Vec<u8>), Vec<u8>> {
+	if msg.len() < HEADER_LEN {
+		return Err(msg);
+	}
+	let channel_id = u32::from_le_bytes([msg[0], msg[1], msg[2], msg[3]]);
+	msg.drain(..HEADER_LEN);
+	Ok((channel_id, msg))
+}
+
+/// Sets multiplexing processor of messages in `wasm_mp1`, replacing whatever
+/// processor was there before.
+///
+pub fn install() {
+	wasm_mp1::set_msg_processor(process_msg);
+}
+
+/// Sets handler of messages that have no channel to go to, returning
+/// previously set one. Without fallback, such messages are dropped.
+///
+pub fn set_fallback(
+	fallback: impl FnMut(Unrouted) + 'static
+) -> Option<Fallback> {
+	MUX.with(|mux| mux.borrow_mut().fallback.replace(Box::new(fallback)))
+}
+
+/// Opens channel with given id, which messages from the outside are given to
+/// `processor`. Returned [`Channel`] sends messages to the outside, and
+/// channel is closed, when it is dropped.
+///
+pub fn open_channel(
+	channel_id: u32, processor: impl FnMut(Vec<u8>) + 'static
+) -> Result<Channel, ChannelInUse> {
+	MUX.with(|mux| {
+		let mut mux = mux.borrow_mut();
+		if mux.channels.contains_key(&channel_id) {
+			return Err(ChannelInUse(channel_id));
+		}
+		mux.channels.insert(channel_id, Some(Box::new(processor)));
+		Ok(Channel { channel_id })
+	})
+}
+
+/// Open channel, created by [`open_channel`].
+///
+pub struct Channel {
+	channel_id: u32,
+}
+
+impl Channel {
+
+	pub fn channel_id(&self) -> u32 {
+		self.channel_id
+	}
+
+	/// Sends given bytes to the outside in this channel.
+	///
+	pub fn send(&self, msg: impl AsRef<[u8]>) {
+		let _ = self.try_send(msg);
+	}
+
+	/// Sends given bytes to the outside in this channel, returning error,
+	/// when embedder rejects message, like `wasm_mp1::try_send_msg_out` does.
+	///
+	pub fn try_send(&self, msg: impl AsRef<[u8]>) -> Result<(), SendError> {
+		wasm_mp1::try_send_parts(&[&self.channel_id.to_le_bytes(), msg.as_ref()])
+	}
+
+}
+
+impl Drop for Channel {
+	fn drop(&mut self) {
+		// thread local may be gone, when thread ends
+		let processor = MUX.try_with(|mux| {
+			mux.borrow_mut().channels.remove(&self.channel_id)
+		});
+		// dropping outside of borrow, as drop may touch channels as well
+		drop(processor);
+	}
+}
+
+/// Routes message from the outside to processor of its channel.
+///
+pub fn process_msg(msg: Vec<u8>) {
+	let (channel_id, body) = match split_channel_id(msg) {
+		Ok(decoded) => decoded,
+		Err(msg) => {
+			fall_back(Unrouted::Malformed(msg));
+			return;
+		},
+	};
+	// processor is called outside of borrow, as it may open and close channels
+	let processor = MUX.with(|mux| {
+		mux.borrow_mut().channels.get_mut(&channel_id).map(Option::take)
+	});
+	match processor {
+		Some(Some(mut processor)) => {
+			processor(body);
+			let closed = MUX.with(|mux| {
+				match mux.borrow_mut().channels.get_mut(&channel_id) {
+					// channel could have been closed and reopened during call
+					Some(slot) if slot.is_none() => {
+						*slot = Some(processor);
+						None
+					},
+					_ => Some(processor),
+				}
+			});
+			drop(closed);
+		},
+		// processors aren't called reentrantly in wasm_mp1
+		Some(None) => (),
+		None => fall_back(Unrouted::UnknownChannel { channel_id, body }),
+	}
+}
+
+fn fall_back(msg: Unrouted) {
+	let fallback = MUX.with(|mux| mux.borrow_mut().fallback.take());
+	let Some(mut fallback) = fallback else {
+		return;
+	};
+	fallback(msg);
+	MUX.with(|mux| {
+		let mut mux = mux.borrow_mut();
+		// fallback could have been replaced during its call
+		if mux.fallback.is_none() {
+			mux.fallback = Some(fallback);
+		}
+	});
+}
@@ -0,0 +1,157 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module reports panics of WASM code to embedder, so that a trap,
+//! which follows panic, can be diagnosed.
+//!
+//! With `panic-hook` feature, [`install`] sets a panic hook that encodes
+//! [`PanicReport`] and gives it to imported `_3nweb_mp1_panic` with pointer
+//! and length of report's bytes, before panic continues into a trap.
+//!
+//! Report is encoded as little-endian `u32` line, column, and length of file
+//! name, followed by file name and panic message, both in UTF-8. Embedder
+//! decodes it with [`PanicReport::decode`].
+//!
+
+use std::fmt;
+
+/// Panic message and location of panic in WASM code.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicReport {
+	pub message: String,
+	pub file: String,
+	pub line: u32,
+	pub column: u32,
+}
+
+impl PanicReport {
+
+	/// Encodes report into bytes, passed to embedder.
+	///
+	pub fn encode(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			12 + self.file.len() + self.message.len()
+		);
+		bytes.extend_from_slice(&self.line.to_le_bytes());
+		bytes.extend_from_slice(&self.column.to_le_bytes());
+		bytes.extend_from_slice(&(self.file.len() as u32).to_le_bytes());
+		bytes.extend_from_slice(self.file.as_bytes());
+		bytes.extend_from_slice(self.message.as_bytes());
+		bytes
+	}
+
+	/// Decodes report from bytes, returning `None` for malformed ones. Invalid
+	/// UTF-8 is replaced, as report is for diagnostics.
+	///
+	pub fn decode(bytes: &[u8]) -> Option<Self> {
+		let field = |i: usize| bytes.get(i..(i + 4))
+		.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+		let line = field(0)?;
+		let column = field(4)?;
+		let file_end = 12usize.checked_add(field(8)? as usize)?;
+		let file = bytes.get(12..file_end)?;
+		let message = &bytes[file_end..];
+		Some(PanicReport {
+			message: String::from_utf8_lossy(message).into_owned(),
+			file: String::from_utf8_lossy(file).into_owned(),
+			line,
+			column,
+		})
+	}
+
+}
+
+impl fmt::Display for PanicReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f, "WASM panicked at {}:{}:{}:\n{}",
+			self.file, self.line, self.column, self.message
+		)
+	}
+}
+
+#[cfg(feature = "panic-hook")]
+pub(crate) mod internals {
+
+	use std::panic::{self, PanicHookInfo};
+	use super::PanicReport;
+
+	fn report_of(info: &PanicHookInfo<'_>) -> PanicReport {
+		let message = info.payload_as_str().unwrap_or("Box<dyn Any>").to_string();
+		match info.location() {
+			Some(location) => PanicReport {
+				message,
+				file: location.file().to_string(),
+				line: location.line(),
+				column: location.column(),
+			},
+			None => PanicReport {
+				message,
+				file: String::new(),
+				line: 0,
+				column: 0,
+			},
+		}
+	}
+
+	/// Sets panic hook, which reports panic to embedder, and then calls
+	/// previously set hook. This is implementation.
+	///
+	pub fn install() {
+		let previous_hook = panic::take_hook();
+		panic::set_hook(Box::new(move |info| {
+			let report = report_of(info).encode();
+			unsafe {
+				_3nweb_mp1_panic(report.as_ptr() as usize, report.len());
+			}
+			previous_hook(info);
+		}));
+	}
+
+	// On native targets with `testing` feature, embedding is mocked in-process.
+	#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+	use crate::testing::mock_env::_3nweb_mp1_panic;
+
+	// This simple classic externing expects to find these functions in `env`
+	// object/namespace imported to WASM by embedding.
+	#[cfg(not(all(feature = "testing", not(target_arch = "wasm32"))))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
+
+		/// Don't use this directly.
+		/// WASM embedding is expected to provide this function in accordance with
+		/// 3nweb's message passing api, version 1, indicated be `_3nweb_mp1_`
+		/// prefix in the name.
+		///
+		/// This function is called by panic hook with pointer `ptr` and length
+		/// `len` of encoded panic report, before WASM traps.
+		///
+		/// Embedder provides this callback in `env` namespace of imports.
+		///
+		fn _3nweb_mp1_panic(ptr: usize, len: usize);
+
+	}
+
+}
+
+/// Sets panic hook, which reports panic message and location to embedder via
+/// imported `_3nweb_mp1_panic`. Previously set hook is called after report.
+///
+#[cfg(feature = "panic-hook")]
+#[inline]
+pub fn install() {
+	internals::install();
+}
@@ -0,0 +1,207 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Storage of message processor, shared by versions of message passing.
+//!
+//! Processor is kept per thread, and it is never called reentrantly:
+//! processor is taken out of storage for the duration of its call, changes of
+//! processor within the call take effect after it returns, and messages that
+//! come within the call are queued.
+//!
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::mem;
+use crate::local::LocalKey;
+
+/// Message processor, owned by this crate, that gets binary messages from the
+/// outside.
+///
+pub type MsgProcessor = Box<dyn FnMut(Vec<u8>)>;
+
+/// Message processor, owned by this crate, that gets binary messages from the
+/// outside as borrowed slices.
+///
+pub type MsgSliceProcessor = Box<dyn FnMut(&[u8])>;
+
+/// Message processor, taking either owned messages, or borrowed slices.
+///
+pub enum Processor {
+	Owned(MsgProcessor),
+	Slice(MsgSliceProcessor),
+}
+
+impl Processor {
+
+	fn process(&mut self, msg: Vec<u8>) {
+		match self {
+			Processor::Owned(processor) => processor(msg),
+			Processor::Slice(processor) => processor(&msg),
+		}
+	}
+
+	fn process_slice(&mut self, msg: &[u8]) {
+		match self {
+			Processor::Owned(processor) => processor(msg.to_vec()),
+			Processor::Slice(processor) => processor(msg),
+		}
+	}
+
+	/// Turns this into processor of owned messages.
+	///
+	pub fn into_owned(self) -> MsgProcessor {
+		match self {
+			Processor::Owned(processor) => processor,
+			Processor::Slice(mut processor) => Box::new(
+				move |msg: Vec<u8>| processor(&msg)
+			),
+		}
+	}
+
+}
+
+/// State of inbound side. Processor is taken out of here for the duration
+/// of its call, so that it is never aliased.
+///
+pub struct Inbound {
+	processor: Option<Processor>,
+	is_processing: bool,
+	replacement: Option<Option<Processor>>,
+	queue: VecDeque<Vec<u8>>,
+}
+
+impl Inbound {
+	pub const fn new() -> Self {
+		Inbound {
+			processor: None,
+			is_processing: false,
+			replacement: None,
+			queue: VecDeque::new(),
+		}
+	}
+}
+
+/// Thread local inbound state.
+///
+pub type InboundKey = LocalKey<RefCell<Inbound>>;
+
+/// Sets a message `processor`, returning previously set one.
+///
+/// When called from within running processor, new processor is recorded as
+/// a replacement that is installed after running one returns. In this case
+/// previously recorded replacement is returned, as running processor can't
+/// be given away.
+///
+pub fn replace_processor(
+	key: &'static InboundKey, processor: Option<Processor>
+) -> Option<Processor> {
+	key.with(|inbound| {
+		let mut inbound = inbound.borrow_mut();
+		if inbound.is_processing {
+			inbound.replacement.replace(processor).flatten()
+		} else {
+			mem::replace(&mut inbound.processor, processor)
+		}
+	})
+}
+
+/// Gives message to processor.
+///
+/// When called from within running processor, message is queued, and it is
+/// given to processor after running call returns, preserving order of
+/// messages.
+///
+pub fn process_msg(key: &'static InboundKey, msg: Vec<u8>) {
+	let is_reentrant = key.with(|inbound| {
+		let mut inbound = inbound.borrow_mut();
+		inbound.queue.push_back(msg);
+		mem::replace(&mut inbound.is_processing, true)
+	});
+	if !is_reentrant {
+		process_queue(key);
+	}
+}
+
+/// Tells if next message can be given to processor as a borrowed slice, i.e.
+/// slice processor is set and it isn't running.
+///
+pub fn takes_slices(key: &'static InboundKey) -> bool {
+	key.with(|inbound| {
+		let inbound = inbound.borrow();
+		!inbound.is_processing
+		&& matches!(inbound.processor, Some(Processor::Slice(_)))
+	})
+}
+
+/// Gives borrowed message to processor, followed by messages that get queued
+/// during this call. This should be called only when processor isn't running.
+///
+pub fn process_slice(key: &'static InboundKey, msg: &[u8]) {
+	let mut processor = key.with(|inbound| {
+		let mut inbound = inbound.borrow_mut();
+		inbound.is_processing = true;
+		inbound.processor.take()
+	});
+	if let Some(processor) = processor.as_mut() {
+		processor.process_slice(msg);
+	}
+	restore_processor(key, processor);
+	process_queue(key);
+}
+
+fn process_queue(key: &'static InboundKey) {
+	loop {
+		let next = key.with(|inbound| {
+			let mut inbound = inbound.borrow_mut();
+			match inbound.queue.pop_front() {
+				Some(msg) => Some((msg, inbound.processor.take())),
+				None => {
+					inbound.is_processing = false;
+					None
+				}
+			}
+		});
+		let Some((msg, mut processor)) = next else {
+			break;
+		};
+		if let Some(processor) = processor.as_mut() {
+			processor.process(msg);
+		}
+		restore_processor(key, processor);
+	}
+}
+
+/// Puts processor back after its call, unless it has been replaced during
+/// the call.
+///
+fn restore_processor(key: &'static InboundKey, processor: Option<Processor>) {
+	let replaced = key.with(|inbound| {
+		let mut inbound = inbound.borrow_mut();
+		match inbound.replacement.take() {
+			Some(replacement) => {
+				inbound.processor = replacement;
+				processor
+			},
+			None => {
+				inbound.processor = processor;
+				None
+			}
+		}
+	});
+	// dropping outside of borrow, as drop may touch processor as well
+	drop(replaced);
+}
@@ -0,0 +1,312 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module provides request/response calls on top of message passing,
+//! version 1, in both directions.
+//!
+//! Every message is framed with a header of 5 bytes: kind of frame (request,
+//! reply or error) in the first byte, followed by call id as little-endian
+//! `u32`. The rest of message is a body. Reply and error frames carry id of
+//! a request that they answer.
+//!
+//! Calls to the outside are made with [`call`], and requests from the outside
+//! are given to a handler, set with [`set_request_handler`]. Both work only
+//! after [`install`] has set this module's processor in `wasm_mp1`.
+//!
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use crate::wasm_mp1;
+
+/// Kind of rpc frame, which is the first byte of a message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+	Request = 1,
+	Reply = 2,
+	Error = 3,
+}
+
+impl FrameKind {
+	fn from_byte(byte: u8) -> Option<Self> {
+		match byte {
+			1 => Some(FrameKind::Request),
+			2 => Some(FrameKind::Reply),
+			3 => Some(FrameKind::Error),
+			_ => None,
+		}
+	}
+}
+
+/// Length of frame's header.
+///
+pub const HEADER_LEN: usize = 5;
+
+/// Rpc frame, as it is passed in a message. Embedder may use this for its
+/// side of calls.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+	pub kind: FrameKind,
+	pub call_id: u32,
+	pub body: Vec<u8>,
+}
+
+impl Frame {
+
+	/// Encodes frame into a message.
+	///
+	pub fn encode(&self) -> Vec<u8> {
+		encode_frame(self.kind, self.call_id, &self.body)
+	}
+
+	/// Decodes frame from a message, returning `None` for messages that are
+	/// not rpc frames.
+	///
+	pub fn decode(mut msg: Vec<u8>) -> Option<Self> {
+		if msg.len() < HEADER_LEN {
+			return None;
+		}
+		let kind = FrameKind::from_byte(msg[0])?;
+		let call_id = u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]]);
+		msg.drain(..HEADER_LEN);
+		Some(Frame { kind, call_id, body: msg })
+	}
+
+}
+
+/// Error of a call to the outside.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+	/// The outside answered with an error frame, body of which is given here.
+	Remote(Vec<u8>),
+	/// Rpc was uninstalled before reply came.
+	Closed,
+}
+
+impl fmt::Display for RpcError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RpcError::Remote(body) => write!(
+				f, "call failed on the other side with {} bytes of error", body.len()
+			),
+			RpcError::Closed => write!(f, "rpc was closed before reply came"),
+		}
+	}
+}
+
+impl std::error::Error for RpcError {}
+
+/// Handler of requests from the outside. Returned `Ok` bytes are sent back
+/// in reply frame, and `Err` bytes are sent back in error frame.
+///
+pub type RequestHandler = Box<dyn FnMut(Vec<u8>) -> Result<Vec<u8>, Vec<u8>>>;
+
+enum PendingCall {
+	Waiting(Option<Waker>),
+	Done(Result<Vec<u8>, RpcError>),
+}
+
+struct Rpc {
+	next_call_id: u32,
+	calls: HashMap<u32, PendingCall>,
+	handler: Option<RequestHandler>,
+}
+
+impl Rpc {
+
+	fn new_call_id(&mut self) -> u32 {
+		loop {
+			let id = self.next_call_id;
+			self.next_call_id = self.next_call_id.wrapping_add(1);
+			if !self.calls.contains_key(&id) {
+				return id;
+			}
+		}
+	}
+
+	/// Records result of a call, returning waker of call's future, which should
+	/// be woken outside of borrow.
+	///
+	fn complete(
+		&mut self, call_id: u32, result: Result<Vec<u8>, RpcError>
+	) -> Option<Waker> {
+		match self.calls.get_mut(&call_id) {
+			Some(call) => match call {
+				PendingCall::Waiting(waker) => {
+					let waker = waker.take();
+					*call = PendingCall::Done(result);
+					waker
+				},
+				PendingCall::Done(_) => None,
+			},
+			None => None,
+		}
+	}
+
+}
+
+thread_local! {
+	static RPC: RefCell<Rpc> = RefCell::new(Rpc {
+		next_call_id: 0,
+		calls: HashMap::new(),
+		handler: None,
+	});
+}
+
+/// Sets rpc processor of messages in `wasm_mp1`, replacing whatever processor
+/// was there before.
+///
+pub fn install() {
+	wasm_mp1::set_msg_processor(process_msg);
+}
+
+/// Removes processor of messages from `wasm_mp1`, which is expected to be rpc
+/// processor, set by [`install`]. Pending calls are completed with
+/// [`RpcError::Closed`].
+///
+pub fn uninstall() {
+	wasm_mp1::take_msg_processor();
+	let wakers = RPC.with(|rpc| {
+		let mut rpc = rpc.borrow_mut();
+		let ids = rpc.calls.keys().copied().collect::<Vec<_>>();
+		ids.into_iter()
+		.filter_map(|id| rpc.complete(id, Err(RpcError::Closed)))
+		.collect::<Vec<_>>()
+	});
+	wakers.into_iter().for_each(Waker::wake);
+}
+
+/// Sets handler of requests from the outside, returning previously set one.
+///
+/// Without handler, requests are answered with error frames with empty body.
+///
+pub fn set_request_handler(
+	handler: impl FnMut(Vec<u8>) -> Result<Vec<u8>, Vec<u8>> + 'static
+) -> Option<RequestHandler> {
+	RPC.with(|rpc| rpc.borrow_mut().handler.replace(Box::new(handler)))
+}
+
+/// Makes a call to the outside with given `request` bytes. Returned future
+/// resolves, when reply or error frame comes for this call.
+///
+/// Dropping returned future forgets the call, and its late reply is ignored.
+///
+pub fn call(request: &[u8]) -> Call {
+	let call_id = RPC.with(|rpc| {
+		let mut rpc = rpc.borrow_mut();
+		let call_id = rpc.new_call_id();
+		rpc.calls.insert(call_id, PendingCall::Waiting(None));
+		call_id
+	});
+	send_frame(FrameKind::Request, call_id, request);
+	Call { call_id }
+}
+
+/// Future of a call to the outside, created by [`call`].
+///
+pub struct Call {
+	call_id: u32,
+}
+
+impl Future for Call {
+	type Output = Result<Vec<u8>, RpcError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		RPC.with(|rpc| {
+			let mut rpc = rpc.borrow_mut();
+			match rpc.calls.remove(&self.call_id) {
+				Some(PendingCall::Done(result)) => Poll::Ready(result),
+				Some(PendingCall::Waiting(_)) => {
+					rpc.calls.insert(
+						self.call_id, PendingCall::Waiting(Some(cx.waker().clone()))
+					);
+					Poll::Pending
+				},
+				None => Poll::Ready(Err(RpcError::Closed)),
+			}
+		})
+	}
+}
+
+impl Drop for Call {
+	fn drop(&mut self) {
+		// thread local may be gone, when thread ends
+		let _ = RPC.try_with(|rpc| {
+			if let Ok(mut rpc) = rpc.try_borrow_mut() {
+				rpc.calls.remove(&self.call_id);
+			}
+		});
+	}
+}
+
+fn encode_frame(kind: FrameKind, call_id: u32, body: &[u8]) -> Vec<u8> {
+	let mut msg = Vec::with_capacity(HEADER_LEN + body.len());
+	msg.push(kind as u8);
+	msg.extend_from_slice(&call_id.to_le_bytes());
+	msg.extend_from_slice(body);
+	msg
+}
+
+fn send_frame(kind: FrameKind, call_id: u32, body: &[u8]) {
+	wasm_mp1::send_msg_out(&encode_frame(kind, call_id, body));
+}
+
+/// Processes message from the outside as an rpc frame. Messages that are not
+/// rpc frames are ignored.
+///
+pub fn process_msg(msg: Vec<u8>) {
+	let Some(frame) = Frame::decode(msg) else {
+		return;
+	};
+	let result = match frame.kind {
+		FrameKind::Reply => Ok(frame.body),
+		FrameKind::Error => Err(RpcError::Remote(frame.body)),
+		FrameKind::Request => {
+			handle_request(frame.call_id, frame.body);
+			return;
+		},
+	};
+	let waker = RPC.with(|rpc| rpc.borrow_mut().complete(frame.call_id, result));
+	if let Some(waker) = waker {
+		waker.wake();
+	}
+}
+
+fn handle_request(call_id: u32, request: Vec<u8>) {
+	// handler is called outside of borrow, as it may make calls itself
+	let handler = RPC.with(|rpc| rpc.borrow_mut().handler.take());
+	let Some(mut handler) = handler else {
+		send_frame(FrameKind::Error, call_id, &[]);
+		return;
+	};
+	let result = handler(request);
+	RPC.with(|rpc| {
+		let mut rpc = rpc.borrow_mut();
+		// handler could have been replaced during its call
+		if rpc.handler.is_none() {
+			rpc.handler = Some(handler);
+		}
+	});
+	match result {
+		Ok(reply) => send_frame(FrameKind::Reply, call_id, &reply),
+		Err(err) => send_frame(FrameKind::Error, call_id, &err),
+	}
+}
@@ -0,0 +1,198 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module runs services, declared with [`mp1_service`] attribute on a
+//! trait, over request/response calls of `rpc`.
+//!
+//! Body of request is method name, prefixed with its length as little-endian
+//! `u16`, followed by tuple of arguments, encoded with a [`Codec`]. Body of
+//! reply is encoded returned value. Body of error frame is a byte of error
+//! code, followed by UTF-8 details, as listed in [`ServiceError`].
+//!
+//! For trait `Foo`, attribute generates `FooDispatcher`, which implements
+//! [`Dispatch`] by calling methods of `Foo` implementation, and `FooClient`,
+//! which has an async method per trait method, making calls with a
+//! [`Transport`]. Guest serves calls from the outside with [`serve`], and makes
+//! calls to the outside with [`RpcTransport`]. Embedder uses the same client
+//! with its own transport, like `ServiceTransport` of `host`.
+//!
+
+use std::fmt;
+use std::future::Future;
+use crate::rpc::{self, RpcError};
+
+pub use crate::typed::Codec;
+
+/// Generates dispatcher and client of a service from a trait definition.
+///
+/// Methods take `&self` or `&mut self`, followed by arguments of owned types
+/// that implement `serde::Serialize` and `serde::de::DeserializeOwned`, as
+/// does returned type.
+///
+pub use wasm_message_passing_3nweb_macros::mp1_service;
+
+/// Error of a call to service.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceError {
+	/// Service has no method with this name. Error code is `1`.
+	UnknownMethod(String),
+	/// Service couldn't decode request. Error code is `2`.
+	BadRequest(String),
+	/// Encoding or decoding failed, either in service with error code `3`,
+	/// or on client's side.
+	Codec(String),
+	/// Error frame that doesn't come from service dispatcher.
+	Remote(Vec<u8>),
+	/// Rpc was closed before reply came.
+	Closed,
+	/// Transport failed to make call, e.g. WASM instance has trapped.
+	Transport(String),
+}
+
+impl ServiceError {
+
+	/// Makes codec error from any displayable error.
+	///
+	pub fn codec(err: impl fmt::Display) -> Self {
+		ServiceError::Codec(err.to_string())
+	}
+
+	/// Encodes this error into body of error frame.
+	///
+	pub fn to_error_body(&self) -> Vec<u8> {
+		let (code, details) = match self {
+			ServiceError::UnknownMethod(method) => (1, method.as_bytes()),
+			ServiceError::BadRequest(details) => (2, details.as_bytes()),
+			ServiceError::Codec(details) => (3, details.as_bytes()),
+			ServiceError::Remote(body) => return body.clone(),
+			ServiceError::Closed | ServiceError::Transport(_) => return Vec::new(),
+		};
+		let mut body = Vec::with_capacity(1 + details.len());
+		body.push(code);
+		body.extend_from_slice(details);
+		body
+	}
+
+	/// Decodes error from body of error frame. Bodies, which don't come from
+	/// service dispatcher, are kept in [`ServiceError::Remote`].
+	///
+	pub fn from_error_body(body: Vec<u8>) -> Self {
+		let details = || String::from_utf8_lossy(&body[1..]).into_owned();
+		match body.first().copied() {
+			Some(1) => ServiceError::UnknownMethod(details()),
+			Some(2) => ServiceError::BadRequest(details()),
+			Some(3) => ServiceError::Codec(details()),
+			_ => ServiceError::Remote(body),
+		}
+	}
+
+}
+
+impl From<RpcError> for ServiceError {
+	fn from(err: RpcError) -> Self {
+		match err {
+			RpcError::Remote(body) => ServiceError::from_error_body(body),
+			RpcError::Closed => ServiceError::Closed,
+		}
+	}
+}
+
+impl fmt::Display for ServiceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ServiceError::UnknownMethod(method) => write!(f, "service has no method {method}"),
+			ServiceError::BadRequest(details) => write!(f, "service couldn't decode request: {details}"),
+			ServiceError::Codec(details) => write!(f, "codec failed: {details}"),
+			ServiceError::Remote(body) => write!(
+				f, "call failed on the other side with {} bytes of error", body.len()
+			),
+			ServiceError::Closed => write!(f, "rpc was closed before reply came"),
+			ServiceError::Transport(details) => write!(f, "transport failed: {details}"),
+		}
+	}
+}
+
+impl std::error::Error for ServiceError {}
+
+/// Encodes body of request to call `method` with encoded arguments.
+///
+pub fn encode_request(method: &str, args: &[u8]) -> Vec<u8> {
+	let mut body = Vec::with_capacity(2 + method.len() + args.len());
+	body.extend_from_slice(&(method.len() as u16).to_le_bytes());
+	body.extend_from_slice(method.as_bytes());
+	body.extend_from_slice(args);
+	body
+}
+
+/// Splits body of request into method name and encoded arguments, returning
+/// `None` for malformed body.
+///
+pub fn decode_request(body: &[u8]) -> Option<(&str, &[u8])> {
+	let len = u16::from_le_bytes([*body.first()?, *body.get(1)?]) as usize;
+	let method = body.get(2..(2 + len))?;
+	let method = std::str::from_utf8(method).ok()?;
+	Some((method, &body[(2 + len)..]))
+}
+
+/// Dispatcher of requests to methods of a service. It is generated by
+/// [`mp1_service`] attribute.
+///
+pub trait Dispatch {
+
+	/// Decodes request, calls service's method, and returns encoded reply.
+	///
+	fn dispatch(&mut self, request: &[u8]) -> Result<Vec<u8>, ServiceError>;
+
+}
+
+/// Sets rpc processor in `wasm_mp1` with request handler, which gives
+/// requests from the outside to `dispatcher`, replacing whatever processor and
+/// handler were there before.
+///
+pub fn serve(mut dispatcher: impl Dispatch + 'static) {
+	rpc::install();
+	rpc::set_request_handler(move |request| {
+		dispatcher.dispatch(&request).map_err(|err| err.to_error_body())
+	});
+}
+
+/// Transport of calls, made by service clients.
+///
+pub trait Transport {
+
+	/// Makes call with given request body, returning future of reply body.
+	/// Error frames are decoded with [`ServiceError::from_error_body`].
+	///
+	fn call(
+		&mut self, request: Vec<u8>
+	) -> impl Future<Output = Result<Vec<u8>, ServiceError>>;
+
+}
+
+/// Transport of guest's calls to a service in the outside with `rpc::call`.
+/// It needs rpc processor, set by `rpc::install`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RpcTransport;
+
+impl Transport for RpcTransport {
+	fn call(
+		&mut self, request: Vec<u8>
+	) -> impl Future<Output = Result<Vec<u8>, ServiceError>> {
+		let call = rpc::call(&request);
+		async move { call.await.map_err(ServiceError::from) }
+	}
+}
@@ -0,0 +1,274 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module mocks WASM embedding in-process, so that code using
+//! `wasm_mp1` and `wasm_mp2` can be unit-tested on native targets.
+//!
+//! With `testing` feature on non-wasm targets, `wasm_mp1::send_msg_out` puts
+//! messages into an outbox, instead of calling embedder's import. Messages are
+//! given to processor with [`inject_msg`], which goes through the same steps
+//! as embedder's call of `_3nweb_mp1_accept_msg`. Similarly, with `mp2`
+//! feature, doorbell of `wasm_mp2` moves messages from outbound ring into the
+//! outbox, and messages are pushed into inbound ring with `inject_mp2_msgs`.
+//!
+//! Mock can reject sent messages, as set by [`reject_sent_msgs`], reporting
+//! rejection to `wasm_mp1::try_send_msg_out` with `try-send` feature.
+//!
+//! With `panic-hook` feature, panic reports from `panic_hook` are kept by mock
+//! and are taken with [`take_panic_report`].
+//!
+//! Mock counts calls across the boundary between WASM and embedder, in either
+//! direction, which are returned by [`boundary_crossings`].
+//!
+//! Like message processor, mock's state is kept per thread, hence, tests that
+//! run in parallel threads don't see each other's messages.
+//!
+
+use std::cell::{Cell, RefCell};
+use crate::wasm_mp1::internals::_3nweb_mp1_accept_msg;
+use crate::wasm_mp1::SendError;
+#[cfg(feature = "mp2")]
+use crate::wasm_mp2::internals::{_3nweb_mp2_doorbell, with_in_ring};
+#[cfg(feature = "mp2")]
+use crate::wasm_mp2::PushError;
+#[cfg(feature = "panic-hook")]
+use crate::panic_hook::PanicReport;
+
+thread_local! {
+	static OUTBOX: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+	static CROSSINGS: Cell<u64> = const { Cell::new(0) };
+	static REJECTION: Cell<Option<SendError>> = const { Cell::new(None) };
+	#[cfg(not(feature = "get-buffer"))]
+	static IN_MSG: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+	#[cfg(all(feature = "refuse-msg", not(feature = "get-buffer")))]
+	static IS_REFUSED: Cell<bool> = const { Cell::new(false) };
+	#[cfg(feature = "panic-hook")]
+	static PANIC_REPORT: RefCell<Option<PanicReport>> = const { RefCell::new(None) };
+}
+
+/// Gives message `msg` to the processor, set in `wasm_mp1`, as embedder's
+/// call of `_3nweb_mp1_accept_msg` would. Returns `false`, when message is
+/// refused for being above maximum size.
+///
+/// With `refuse-msg` feature, this panics, unless message is either asked
+/// for, or refused with `_3nweb_mp1_refuse_msg`.
+///
+#[cfg(not(feature = "get-buffer"))]
+pub fn inject_msg(msg: Vec<u8>) -> bool {
+	let len = msg.len();
+	IN_MSG.with(|in_msg| in_msg.replace(Some(msg)));
+	count_crossing();
+	_3nweb_mp1_accept_msg(len);
+	// message that wasn't asked for is refused
+	let is_taken = IN_MSG.with(|in_msg| in_msg.take()).is_none();
+	#[cfg(feature = "refuse-msg")]
+	assert_ne!(
+		is_taken, IS_REFUSED.with(|is_refused| is_refused.replace(false)),
+		"message should be either taken, or refused"
+	);
+	is_taken
+}
+
+/// Gives message `msg` to the processor, set in `wasm_mp1`, as embedder
+/// would with calls of `_3nweb_mp1_get_buffer` and `_3nweb_mp1_accept_msg`.
+/// Returns `false`, when message is refused for being above maximum size.
+///
+#[cfg(feature = "get-buffer")]
+pub fn inject_msg(msg: Vec<u8>) -> bool {
+	count_crossing();
+	let ptr = crate::wasm_mp1::internals::_3nweb_mp1_get_buffer(msg.len());
+	if ptr == 0 {
+		return false;
+	}
+	unsafe {
+		std::ptr::copy_nonoverlapping(msg.as_ptr(), ptr as *mut u8, msg.len());
+	}
+	count_crossing();
+	_3nweb_mp1_accept_msg(msg.len());
+	true
+}
+
+/// Pushes messages into inbound ring of `wasm_mp2`, ringing its doorbell when
+/// ring gets full, and after the last message, as embedder would.
+///
+/// Panics on message that can't fit into inbound ring.
+///
+#[cfg(feature = "mp2")]
+pub fn inject_mp2_msgs(msgs: impl IntoIterator<Item = Vec<u8>>) {
+	for msg in msgs {
+		if with_in_ring(|ring| ring.push(&msg)) == Err(PushError::Full) {
+			count_crossing();
+			_3nweb_mp2_doorbell();
+			with_in_ring(|ring| ring.push(&msg))
+			.expect("message should fit into emptied inbound ring");
+		}
+	}
+	count_crossing();
+	_3nweb_mp2_doorbell();
+}
+
+/// Makes mocked embedding reject messages, sent with `wasm_mp1`, with given
+/// `error`, till this is called with `None`. Rejected messages don't get into
+/// the outbox.
+///
+pub fn reject_sent_msgs(error: Option<SendError>) {
+	REJECTION.with(|rejection| rejection.set(error));
+}
+
+/// Takes all messages that have reached mocked embedding from `wasm_mp1` and
+/// `wasm_mp2` since previous call, in order of arrival.
+///
+pub fn take_sent_msgs() -> Vec<Vec<u8>> {
+	OUTBOX.with(|outbox| outbox.take())
+}
+
+/// Takes panic report, which has reached mocked embedding from panic hook,
+/// set by `panic_hook::install`. Panic on native targets unwinds, and can be
+/// caught with `std::panic::catch_unwind`, before report is taken.
+///
+#[cfg(feature = "panic-hook")]
+pub fn take_panic_report() -> Option<PanicReport> {
+	PANIC_REPORT.with(|report| report.take())
+}
+
+/// Returns number of calls across the boundary between WASM and mocked
+/// embedding, made in this thread.
+///
+pub fn boundary_crossings() -> u64 {
+	CROSSINGS.with(Cell::get)
+}
+
+fn count_crossing() {
+	CROSSINGS.with(|crossings| crossings.set(crossings.get() + 1));
+}
+
+/// Mock implementations of imports, expected by `wasm_mp1` and `wasm_mp2` in
+/// `env` namespace.
+///
+pub(crate) mod mock_env {
+
+	use super::{OUTBOX, REJECTION, count_crossing};
+	use crate::wasm_mp1::SendError;
+	#[cfg(not(feature = "get-buffer"))]
+	use super::IN_MSG;
+	#[cfg(all(feature = "refuse-msg", not(feature = "get-buffer")))]
+	use super::IS_REFUSED;
+	#[cfg(feature = "mp2")]
+	use crate::wasm_mp2::internals::with_out_ring;
+	use std::cell::Cell;
+
+	/// Copies message, made of given memory areas, into the outbox, unless
+	/// messages are rejected.
+	///
+	unsafe fn take_sent_msg(
+		parts: impl IntoIterator<Item = (usize, usize)>
+	) -> Result<(), SendError> {
+		count_crossing();
+		if let Some(error) = REJECTION.with(Cell::get) {
+			return Err(error);
+		}
+		let mut msg = Vec::new();
+		for (ptr, len) in parts {
+			msg.extend_from_slice(unsafe {
+				std::slice::from_raw_parts(ptr as *const u8, len)
+			});
+		}
+		OUTBOX.with(|outbox| outbox.borrow_mut().push(msg));
+		Ok(())
+	}
+
+	/// Mock of embedder's `_3nweb_mp1_send_out_msg`, which copies message into
+	/// the outbox.
+	///
+	#[cfg(not(feature = "try-send"))]
+	pub unsafe fn _3nweb_mp1_send_out_msg(ptr: usize, len: usize) {
+		let _ = unsafe { take_sent_msg([(ptr, len)]) };
+	}
+
+	/// Mock of embedder's `_3nweb_mp1_try_send_out_msg`, which copies message
+	/// into the outbox, or reports rejection.
+	///
+	#[cfg(feature = "try-send")]
+	pub unsafe fn _3nweb_mp1_try_send_out_msg(ptr: usize, len: usize) -> u32 {
+		match unsafe { take_sent_msg([(ptr, len)]) } {
+			Ok(()) => SendError::STATUS_OK,
+			Err(error) => error.status(),
+		}
+	}
+
+	/// Mock of embedder's `_3nweb_mp1_send_out_msg_parts`, which gathers parts
+	/// into one message in the outbox, or reports rejection. Pointers and
+	/// lengths of parts are native `usize` pairs, as WASM's `u32` pairs are
+	/// `usize` in 32-bit WASM.
+	///
+	#[cfg(feature = "send-parts")]
+	pub unsafe fn _3nweb_mp1_send_out_msg_parts(
+		parts_ptr: usize, num_of_parts: usize
+	) -> u32 {
+		let parts = unsafe {
+			std::slice::from_raw_parts(parts_ptr as *const usize, 2 * num_of_parts)
+		};
+		let parts = parts.chunks_exact(2).map(|pair| (pair[0], pair[1]));
+		match unsafe { take_sent_msg(parts) } {
+			Ok(()) => SendError::STATUS_OK,
+			Err(error) => error.status(),
+		}
+	}
+
+	/// Mock of embedder's `_3nweb_mp1_write_msg_into`, which copies message,
+	/// given to [`inject_msg`](super::inject_msg), into provided buffer.
+	///
+	#[cfg(not(feature = "get-buffer"))]
+	pub unsafe fn _3nweb_mp1_write_msg_into(ptr: usize) {
+		count_crossing();
+		let msg = IN_MSG.with(|in_msg| in_msg.take())
+		.expect("there should be a message injected");
+		unsafe {
+			std::ptr::copy_nonoverlapping(msg.as_ptr(), ptr as *mut u8, msg.len());
+		}
+	}
+
+	/// Mock of embedder's `_3nweb_mp1_refuse_msg`, which marks injected
+	/// message as refused.
+	///
+	#[cfg(all(feature = "refuse-msg", not(feature = "get-buffer")))]
+	pub unsafe fn _3nweb_mp1_refuse_msg(_len: usize) {
+		count_crossing();
+		IS_REFUSED.with(|is_refused| is_refused.set(true));
+	}
+
+	/// Mock of embedder's `_3nweb_mp1_panic`, which keeps decoded report.
+	///
+	#[cfg(feature = "panic-hook")]
+	pub unsafe fn _3nweb_mp1_panic(ptr: usize, len: usize) {
+		count_crossing();
+		let bytes = unsafe { std::slice::from_raw_parts(ptr as *const u8, len) };
+		let report = crate::panic_hook::PanicReport::decode(bytes);
+		super::PANIC_REPORT.with(|cell| cell.replace(report));
+	}
+
+	/// Mock of embedder's `_3nweb_mp2_ring_doorbell`, which moves messages from
+	/// outbound ring into the outbox.
+	///
+	#[cfg(feature = "mp2")]
+	pub unsafe fn _3nweb_mp2_ring_doorbell() {
+		count_crossing();
+		let msgs = with_out_ring(|ring| {
+			std::iter::from_fn(|| ring.pop()).collect::<Vec<_>>()
+		});
+		OUTBOX.with(|outbox| outbox.borrow_mut().extend(msgs));
+	}
+
+}
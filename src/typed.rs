@@ -0,0 +1,193 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module passes serde-typed messages, serialized with a pluggable
+//! [`Codec`].
+//!
+//! Codecs are enabled by cargo features:
+//! - `json` enables [`JsonCodec`],
+//! - `cbor` enables [`CborCodec`],
+//! - `msgpack` enables [`MsgPackCodec`],
+//! - `bincode` enables [`BincodeCodec`].
+//!
+//! Messages that can't be decoded are given to an error handler together with
+//! decoding error, instead of panicking.
+//!
+
+use std::fmt;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::wasm_mp1;
+
+/// Serialization format of typed messages.
+///
+pub trait Codec {
+	type EncodeError: fmt::Debug + fmt::Display;
+	type DecodeError: fmt::Debug + fmt::Display;
+
+	fn encode<T: Serialize + ?Sized>(
+		&self, value: &T
+	) -> Result<Vec<u8>, Self::EncodeError>;
+
+	fn decode<T: DeserializeOwned>(
+		&self, bytes: &[u8]
+	) -> Result<T, Self::DecodeError>;
+}
+
+/// Message from the outside that failed decoding.
+///
+#[derive(Debug)]
+pub struct DecodeFailure<E> {
+	/// Decoding error from codec.
+	pub error: E,
+	/// Bytes of message that failed decoding.
+	pub msg: Vec<u8>,
+}
+
+/// Serializes given `value` with `codec` and sends it to the outside.
+///
+pub fn send<T: Serialize + ?Sized, C: Codec>(
+	codec: &C, value: &T
+) -> Result<(), C::EncodeError> {
+	let msg = codec.encode(value)?;
+	wasm_mp1::send_msg_out(&msg);
+	Ok(())
+}
+
+/// Wraps typed `processor` into a processor of binary messages, which can be
+/// set in `wasm_mp1`, or used by other layers. Messages that fail decoding with
+/// `codec` are given to `err_handler`.
+///
+pub fn typed_processor<T: DeserializeOwned, C: Codec>(
+	codec: C,
+	mut processor: impl FnMut(T),
+	mut err_handler: impl FnMut(DecodeFailure<C::DecodeError>),
+) -> impl FnMut(Vec<u8>) {
+	move |msg: Vec<u8>| match codec.decode(&msg) {
+		Ok(value) => processor(value),
+		Err(error) => err_handler(DecodeFailure { error, msg }),
+	}
+}
+
+/// Sets in `wasm_mp1` a processor that decodes messages with `codec` and gives
+/// them to typed `processor`. Messages that fail decoding are given to
+/// `err_handler`.
+///
+pub fn set_typed_processor<T: DeserializeOwned + 'static, C: Codec + 'static>(
+	codec: C,
+	processor: impl FnMut(T) + 'static,
+	err_handler: impl FnMut(DecodeFailure<C::DecodeError>) + 'static,
+) {
+	wasm_mp1::set_msg_processor(typed_processor(codec, processor, err_handler));
+}
+
+/// JSON codec, based on `serde_json`.
+///
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl Codec for JsonCodec {
+	type EncodeError = serde_json::Error;
+	type DecodeError = serde_json::Error;
+
+	fn encode<T: Serialize + ?Sized>(
+		&self, value: &T
+	) -> Result<Vec<u8>, Self::EncodeError> {
+		serde_json::to_vec(value)
+	}
+
+	fn decode<T: DeserializeOwned>(
+		&self, bytes: &[u8]
+	) -> Result<T, Self::DecodeError> {
+		serde_json::from_slice(bytes)
+	}
+}
+
+/// CBOR codec, based on `ciborium`.
+///
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+	type EncodeError = ciborium::ser::Error<std::io::Error>;
+	type DecodeError = ciborium::de::Error<std::io::Error>;
+
+	fn encode<T: Serialize + ?Sized>(
+		&self, value: &T
+	) -> Result<Vec<u8>, Self::EncodeError> {
+		let mut bytes = Vec::new();
+		ciborium::into_writer(value, &mut bytes)?;
+		Ok(bytes)
+	}
+
+	fn decode<T: DeserializeOwned>(
+		&self, bytes: &[u8]
+	) -> Result<T, Self::DecodeError> {
+		ciborium::from_reader(bytes)
+	}
+}
+
+/// MessagePack codec, based on `rmp-serde`. Structs are encoded as maps with
+/// field names.
+///
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPackCodec {
+	type EncodeError = rmp_serde::encode::Error;
+	type DecodeError = rmp_serde::decode::Error;
+
+	fn encode<T: Serialize + ?Sized>(
+		&self, value: &T
+	) -> Result<Vec<u8>, Self::EncodeError> {
+		rmp_serde::to_vec_named(value)
+	}
+
+	fn decode<T: DeserializeOwned>(
+		&self, bytes: &[u8]
+	) -> Result<T, Self::DecodeError> {
+		rmp_serde::from_slice(bytes)
+	}
+}
+
+/// Bincode codec, based on `bincode` with its default options.
+///
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl Codec for BincodeCodec {
+	type EncodeError = bincode::Error;
+	type DecodeError = bincode::Error;
+
+	fn encode<T: Serialize + ?Sized>(
+		&self, value: &T
+	) -> Result<Vec<u8>, Self::EncodeError> {
+		bincode::serialize(value)
+	}
+
+	fn decode<T: DeserializeOwned>(
+		&self, bytes: &[u8]
+	) -> Result<T, Self::DecodeError> {
+		bincode::deserialize(bytes)
+	}
+}
@@ -22,43 +22,247 @@
 //! - To send messages outside, WASM uses imported `_3nweb_mp1_send_out_msg`
 //! during which call embeddder must read message from identified memory area.
 //! 
+//! - With `try-send` feature, WASM instead uses imported
+//! `_3nweb_mp1_try_send_out_msg`, which is the same as `_3nweb_mp1_send_out_msg`,
+//! but returns status code, telling if embedder has taken the message. Status
+//! codes are listed in [`SendError`]. This switch is made at compile time, and
+//! legacy `_3nweb_mp1_send_out_msg` isn't imported with this feature, hence,
+//! such WASM needs embedder that provides the new import.
+//! 
+//! - With `send-parts` feature, message, made of several memory areas, is sent
+//! with imported `_3nweb_mp1_send_out_msg_parts`, during which embedder
+//! gathers areas into one message.
+//! 
 //! - To send messages inside, embedder uses exported from WASM
 //! `_3nweb_mp1_accept_msg`. During this call, WASM calls back embedder's
 //! imported `_3nweb_mp1_write_msg_into`, where embedder actually copies data
 //! into provided memory area.
 //! 
+//! - With `get-buffer` feature, messages are sent inside as README describes.
+//! Embedder asks WASM to allocate memory for message with exported
+//! `_3nweb_mp1_get_buffer`, copies message into it, and calls exported
+//! `_3nweb_mp1_accept_msg`. There is no `_3nweb_mp1_write_msg_into` import in
+//! this mode.
+//! 
+//! Messages from the outside, which are longer than maximum size, set with
+//! [`set_max_in_msg_size`], are refused before memory is allocated for them.
+//! WASM doesn't call `_3nweb_mp1_write_msg_into` for refused message, and
+//! `_3nweb_mp1_get_buffer` returns zero for it, so that embedder learns about
+//! refusal.
+//! 
+//! - With `refuse-msg` feature, WASM, that doesn't use `get-buffer`, signals
+//! refusal explicitly with imported `_3nweb_mp1_refuse_msg`, called with
+//! length of refused message within `_3nweb_mp1_accept_msg` call.
+//! 
+//! Functions are exported with `#[wasm_bindgen]`, by default. With `raw-abi`
+//! feature, they are exported as plain `#[no_mangle]` symbols, and wasm-bindgen
+//! isn't needed at all, which suits embedders without JS glue, like wasmtime.
+//! Imports are taken from `env` module in either case. Exports use
+//! `"C-unwind"` ABI, which is the same as `"C"` in WASM, but lets panics of
+//! processors unwind in native tests with `testing` feature.
+//! 
+//! This module needs only `alloc`, and it is usable under `no_std`, when
+//! `std` feature is off.
+//! 
+//! Message processor is kept per thread, and it is never called reentrantly:
+//! 
+//! - When message processor sets, replaces or takes processor, change takes
+//! effect after current call returns, and running processor is dropped at that
+//! point, if it has been replaced.
+//! 
+//! - When message comes while processor is running (e.g. embedder sends it in
+//! response to `send_msg_out` call within processor), message is queued and
+//! given to processor after current call returns.
+//! 
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
 
-mod internals {
+pub(crate) mod internals {
 
+	#[cfg(not(feature = "raw-abi"))]
 	use wasm_bindgen::prelude::*;
 
+	use super::SendError;
+
 	/// Sends given binary message to the outside. This is implementation.
 	/// 
-	pub fn send_msg_out(msg: &Vec<u8>) -> () {
+	#[cfg(not(feature = "try-send"))]
+	pub fn send_msg_out(msg: &[u8]) {
 		unsafe {
 			_3nweb_mp1_send_out_msg(msg.as_ptr() as usize, msg.len());
 		}
 	}
 
-	#[allow(dead_code)]
-	static mut MSG_PROCESSOR: Option<&dyn Fn(Vec<u8>) -> ()> = None;
+	/// Sends given binary message to the outside, ignoring embedder's status.
+	/// This is implementation.
+	/// 
+	#[cfg(feature = "try-send")]
+	pub fn send_msg_out(msg: &[u8]) {
+		let _ = try_send_msg_out(msg);
+	}
 
-	/// Sets a message `processor` function/closure that will be called with
-	/// binary messages from the outside. This is implementation.
+	/// Sends given binary message to the outside, returning embedder's status.
+	/// This is implementation.
 	/// 
-	/// Messages are given to `processor` as `Vec<u8>` completely separated from
-	/// workings of message exchange buffer(s).
+	#[cfg(feature = "try-send")]
+	pub fn try_send_msg_out(msg: &[u8]) -> Result<(), SendError> {
+		let status = unsafe {
+			_3nweb_mp1_try_send_out_msg(msg.as_ptr() as usize, msg.len())
+		};
+		SendError::from_status(status)
+	}
+
+	/// Sends given binary message to the outside with legacy import, which
+	/// has no status. This is implementation.
 	/// 
-	pub fn set_msg_processor(processor: &'static dyn Fn(Vec<u8>) -> ()) -> () {
-		unsafe {
-			MSG_PROCESSOR = Some(processor);
+	#[cfg(not(feature = "try-send"))]
+	pub fn try_send_msg_out(msg: &[u8]) -> Result<(), SendError> {
+		send_msg_out(msg);
+		Ok(())
+	}
+
+	use alloc::vec::Vec;
+	use core::cell::RefCell;
+	use crate::processor::{self, Inbound, Processor};
+
+	thread_local! {
+		static INBOUND: RefCell<Inbound> = const { RefCell::new(Inbound::new()) };
+		/// Receive buffer, recycled between messages for slice processor.
+		static RECV_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+		/// Send buffer, recycled between messages, sent in parts. It holds
+		/// either coalesced message, or pointers and lengths of its parts as
+		/// `usize`, which is `u32` in 32-bit WASM, and is native pointer size
+		/// with mocked embedding.
+		#[cfg(not(feature = "send-parts"))]
+		static SEND_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+		#[cfg(feature = "send-parts")]
+		static SEND_BUFFER: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+	}
+
+	/// Sends one message, made of given parts, to the outside, coalescing
+	/// parts in send buffer. This is implementation.
+	/// 
+	#[cfg(not(feature = "send-parts"))]
+	pub fn try_send_parts(parts: &[&[u8]]) -> Result<(), SendError> {
+		// buffer is taken out for the call, as embedder may reenter
+		let mut buffer = SEND_BUFFER.with(|buffer| buffer.take());
+		buffer.clear();
+		for part in parts {
+			buffer.extend_from_slice(part);
+		}
+		let result = try_send_msg_out(&buffer);
+		SEND_BUFFER.with(|send_buffer| send_buffer.replace(buffer));
+		result
+	}
+
+	/// Sends one message, made of given parts, to the outside with
+	/// `_3nweb_mp1_send_out_msg_parts` import. This is implementation.
+	/// 
+	#[cfg(feature = "send-parts")]
+	pub fn try_send_parts(parts: &[&[u8]]) -> Result<(), SendError> {
+		// buffer is taken out for the call, as embedder may reenter
+		let mut buffer = SEND_BUFFER.with(|buffer| buffer.take());
+		buffer.clear();
+		for part in parts {
+			buffer.push(part.as_ptr() as usize);
+			buffer.push(part.len());
 		}
+		let status = unsafe {
+			_3nweb_mp1_send_out_msg_parts(buffer.as_ptr() as usize, parts.len())
+		};
+		SEND_BUFFER.with(|send_buffer| send_buffer.replace(buffer));
+		SendError::from_status(status)
+	}
+
+	/// Sets a message `processor` function/closure that will be called with
+	/// binary messages from the outside, returning previously set processor.
+	/// This is implementation.
+	/// 
+	pub fn replace_msg_processor(
+		processor: Option<Processor>
+	) -> Option<Processor> {
+		processor::replace_processor(&INBOUND, processor)
+	}
+
+	/// Gives message to processor. This is implementation.
+	/// 
+	pub fn process_msg(msg: Vec<u8>) {
+		processor::process_msg(&INBOUND, msg);
+	}
+
+	/// Tells if incoming message of given length is above maximum size, set in
+	/// `mp_versions`.
+	/// 
+	fn is_too_large(len: usize) -> bool {
+		crate::mp_versions::internals::max_msg_size()
+		.is_some_and(|max| len > (max as usize))
+	}
+
+	/// Tells if next message can go to slice processor via receive buffer.
+	/// 
+	fn takes_slices() -> bool {
+		processor::takes_slices(&INBOUND)
+	}
+
+	/// Grows receive buffer to be at least `len` long, returning pointer to it.
+	/// Buffer's allocation is reused, and only growth is zeroed.
+	/// 
+	fn prepare_recv_buffer(len: usize) -> usize {
+		RECV_BUFFER.with(|buffer| {
+			let mut buffer = buffer.borrow_mut();
+			if buffer.len() < len {
+				buffer.resize(len, 0);
+			}
+			buffer.as_ptr() as usize
+		})
 	}
 
-	/// This simple classic externing expects to find these functions in `env`
-	/// object/namespace imported to WASM by embedding.
+	/// Gives first `len` bytes of receive buffer to slice processor.
 	/// 
-	extern {
+	fn process_recv_buffer(len: usize) {
+		// buffer is taken out for the call, and reentrant messages are queued
+		let buffer = RECV_BUFFER.with(|buffer| buffer.take());
+		processor::process_slice(&INBOUND, &buffer[..len]);
+		RECV_BUFFER.with(|recv_buffer| recv_buffer.replace(buffer));
+	}
+
+	// On native targets with `testing` feature, embedding is mocked in-process.
+	#[cfg(all(
+		feature = "testing", not(target_arch = "wasm32"),
+		not(feature = "try-send")
+	))]
+	use crate::testing::mock_env::_3nweb_mp1_send_out_msg;
+	#[cfg(all(
+		feature = "testing", not(target_arch = "wasm32"),
+		feature = "try-send"
+	))]
+	use crate::testing::mock_env::_3nweb_mp1_try_send_out_msg;
+	#[cfg(all(
+		feature = "testing", not(target_arch = "wasm32"),
+		feature = "send-parts"
+	))]
+	use crate::testing::mock_env::_3nweb_mp1_send_out_msg_parts;
+	#[cfg(all(
+		feature = "testing", not(target_arch = "wasm32"),
+		not(feature = "get-buffer")
+	))]
+	use crate::testing::mock_env::_3nweb_mp1_write_msg_into;
+	#[cfg(all(
+		feature = "testing", not(target_arch = "wasm32"),
+		feature = "refuse-msg", not(feature = "get-buffer")
+	))]
+	use crate::testing::mock_env::_3nweb_mp1_refuse_msg;
+
+	// This simple classic externing expects to find these functions in `env`
+	// object/namespace imported to WASM by embedding.
+	#[cfg(not(any(
+		all(feature = "testing", not(target_arch = "wasm32")),
+		feature = "try-send"
+	)))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
 
 		/// Don't use this directly.
 		/// WASM embedding is expected to provide this function in accordance with
@@ -72,6 +276,62 @@ mod internals {
 		/// 
 		fn _3nweb_mp1_send_out_msg(ptr: usize, len: usize);
 
+	}
+
+	#[cfg(all(
+		not(all(feature = "testing", not(target_arch = "wasm32"))),
+		feature = "try-send"
+	))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
+
+		/// Don't use this directly.
+		/// WASM embedding is expected to provide this function in accordance with
+		/// 3nweb's message passing api, version 1, indicated be `_3nweb_mp1_`
+		/// prefix in the name.
+		/// 
+		/// This function is called like `_3nweb_mp1_send_out_msg`, and it
+		/// returns status code: `0`, when embedder has taken the message, or an
+		/// error code, listed in [`SendError`].
+		/// 
+		/// Embedder provides this callback in `env` namespace of imports.
+		/// 
+		fn _3nweb_mp1_try_send_out_msg(ptr: usize, len: usize) -> u32;
+
+	}
+
+	#[cfg(all(
+		not(all(feature = "testing", not(target_arch = "wasm32"))),
+		feature = "send-parts"
+	))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
+
+		/// Don't use this directly.
+		/// WASM embedding is expected to provide this function in accordance with
+		/// 3nweb's message passing api, version 1, indicated be `_3nweb_mp1_`
+		/// prefix in the name.
+		/// 
+		/// This function is called to tell embedding that one message for the
+		/// outside is made of `num_of_parts` memory areas, which pointers and
+		/// lengths are given as pairs of `usize` at pointer `parts_ptr`, i.e.
+		/// pairs of `u32` in 32-bit WASM. Embedder must copy parts in order
+		/// during this call. Returned status code is the same as in
+		/// `_3nweb_mp1_try_send_out_msg`.
+		/// 
+		/// Embedder provides this callback in `env` namespace of imports.
+		/// 
+		fn _3nweb_mp1_send_out_msg_parts(parts_ptr: usize, num_of_parts: usize) -> u32;
+
+	}
+
+	#[cfg(not(any(
+		all(feature = "testing", not(target_arch = "wasm32")),
+		feature = "get-buffer"
+	)))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
+
 		/// Don't use this directly.
 		/// WASM embedding is expected to provide this function in accordance with
 		/// 3nweb's message passing api, version 1, indicated be `_3nweb_mp1_`
@@ -88,6 +348,29 @@ mod internals {
 
 	}
 
+	#[cfg(all(
+		not(all(feature = "testing", not(target_arch = "wasm32"))),
+		feature = "refuse-msg", not(feature = "get-buffer")
+	))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
+
+		/// Don't use this directly.
+		/// WASM embedding is expected to provide this function in accordance with
+		/// 3nweb's message passing api, version 1, indicated be `_3nweb_mp1_`
+		/// prefix in the name.
+		/// 
+		/// This function is called within exported `_3nweb_mp1_accept_msg`,
+		/// instead of `_3nweb_mp1_write_msg_into`, to tell embedding that message
+		/// with length `len` is refused for being above maximum size, and that
+		/// embedder should drop it.
+		/// 
+		/// Embedder provides this callback in `env` namespace of imports.
+		/// 
+		fn _3nweb_mp1_refuse_msg(len: usize);
+
+	}
+
 	/// Don't use this directly.
 	/// This function is exported from WASM in accordance with 3nweb's message
 	/// passing api, version 1, indicated be `_3nweb_mp1_` prefix in the name.
@@ -97,34 +380,288 @@ mod internals {
 	/// imported `_3nweb_mp1_write_msg_into`. When callback returns, message is
 	/// given to processor.
 	/// 
-	#[wasm_bindgen]
-	pub fn _3nweb_mp1_accept_msg(len: usize) -> () {
-		let mut msg = Vec::with_capacity(len);
-		unsafe {
-			_3nweb_mp1_write_msg_into(msg.as_ptr() as usize);
-			msg.set_len(len);
-			if MSG_PROCESSOR.is_some() {
-				(MSG_PROCESSOR.as_ref().unwrap())(msg);
+	/// Message above maximum size is refused before allocation, and then
+	/// `_3nweb_mp1_write_msg_into` isn't called, which tells embedder that
+	/// message has been dropped. With `refuse-msg` feature, imported
+	/// `_3nweb_mp1_refuse_msg` is called for it instead.
+	/// 
+	#[cfg(not(feature = "get-buffer"))]
+	#[cfg_attr(not(feature = "raw-abi"), wasm_bindgen)]
+	#[cfg_attr(feature = "raw-abi", no_mangle)]
+	pub extern "C-unwind" fn _3nweb_mp1_accept_msg(len: usize) {
+		if is_too_large(len) {
+			#[cfg(feature = "refuse-msg")]
+			unsafe {
+				_3nweb_mp1_refuse_msg(len);
+			}
+			return;
+		}
+		if takes_slices() {
+			let ptr = prepare_recv_buffer(len);
+			unsafe {
+				_3nweb_mp1_write_msg_into(ptr);
 			}
+			process_recv_buffer(len);
+		} else {
+			let mut msg = Vec::with_capacity(len);
+			unsafe {
+				_3nweb_mp1_write_msg_into(msg.as_ptr() as usize);
+				msg.set_len(len);
+			}
+			process_msg(msg);
+		}
+	}
+
+	/// Buffer, given out by `_3nweb_mp1_get_buffer`.
+	/// 
+	#[cfg(feature = "get-buffer")]
+	enum InBuffer {
+		Fresh(Vec<u8>),
+		Recv(usize),
+	}
+
+	#[cfg(feature = "get-buffer")]
+	thread_local! {
+		static IN_BUFFER: RefCell<Option<InBuffer>> = const { RefCell::new(None) };
+	}
+
+	/// Don't use this directly.
+	/// This function is exported from WASM in accordance with 3nweb's message
+	/// passing api, version 1, indicated be `_3nweb_mp1_` prefix in the name.
+	/// 
+	/// This is called by WASM embedding with `len` size of the message, before
+	/// embedder copies message into returned memory area. Buffer is kept till
+	/// following `_3nweb_mp1_accept_msg` call. Receive buffer is given out, when
+	/// slice processor is set.
+	/// 
+	/// Message above maximum size is refused before allocation with zero
+	/// pointer, and then embedder must neither write message, nor call
+	/// `_3nweb_mp1_accept_msg`.
+	/// 
+	#[cfg(feature = "get-buffer")]
+	#[cfg_attr(not(feature = "raw-abi"), wasm_bindgen)]
+	#[cfg_attr(feature = "raw-abi", no_mangle)]
+	pub extern "C-unwind" fn _3nweb_mp1_get_buffer(len: usize) -> usize {
+		if is_too_large(len) {
+			IN_BUFFER.with(|in_buffer| in_buffer.take());
+			return 0;
+		}
+		let (buffer, ptr) = if takes_slices() {
+			(InBuffer::Recv(len), prepare_recv_buffer(len))
+		} else {
+			let buffer = alloc::vec![0u8; len];
+			let ptr = buffer.as_ptr() as usize;
+			(InBuffer::Fresh(buffer), ptr)
+		};
+		IN_BUFFER.with(|in_buffer| in_buffer.replace(Some(buffer)));
+		ptr
+	}
+
+	/// Don't use this directly.
+	/// This function is exported from WASM in accordance with 3nweb's message
+	/// passing api, version 1, indicated be `_3nweb_mp1_` prefix in the name.
+	/// 
+	/// This is called by WASM embedding with `len` size of the message, after
+	/// embedder has copied message into buffer from `_3nweb_mp1_get_buffer`.
+	/// Message is given to processor. Call without buffer, or with `len` larger
+	/// than buffer, is ignored.
+	/// 
+	#[cfg(feature = "get-buffer")]
+	#[cfg_attr(not(feature = "raw-abi"), wasm_bindgen)]
+	#[cfg_attr(feature = "raw-abi", no_mangle)]
+	pub extern "C-unwind" fn _3nweb_mp1_accept_msg(len: usize) {
+		let buffer = IN_BUFFER.with(|in_buffer| in_buffer.take());
+		match buffer {
+			Some(InBuffer::Fresh(mut msg)) if len <= msg.len() => {
+				msg.truncate(len);
+				process_msg(msg);
+			},
+			Some(InBuffer::Recv(buffer_len)) if len <= buffer_len => {
+				process_recv_buffer(len);
+			},
+			_ => (),
 		}
 	}
 
 }
 
-/// Sends given binary message to the outside.
+/// Sets maximum size of messages from the outside, refusing longer ones
+/// before allocation. `None` means no limit, which is default. Limit is also
+/// advertised to embedder in versions descriptor of `mp_versions`.
 /// 
 #[inline]
-pub fn send_msg_out(msg: &Vec<u8>) -> () {
+pub fn set_max_in_msg_size(max_size: Option<u32>) {
+	crate::mp_versions::internals::set_max_msg_size(max_size);
+}
+
+/// Sends given binary message to the outside. Message is any byte slice, as
+/// with [`send`].
+/// 
+#[inline]
+pub fn send_msg_out(msg: &[u8]) {
 	internals::send_msg_out(msg);
 }
 
+/// Sends given bytes to the outside as a binary message. This accepts any
+/// byte source, like slice, array, `Vec<u8>` or `String`, without copying it.
+/// 
+#[inline]
+pub fn send(msg: impl AsRef<[u8]>) {
+	internals::send_msg_out(msg.as_ref());
+}
+
+/// Sends one binary message, made of given `parts`, to the outside.
+/// 
+/// With `send-parts` feature, embedder gathers parts with
+/// `_3nweb_mp1_send_out_msg_parts` import, and there is no concatenation in
+/// WASM. Without it, parts are coalesced in a send buffer, which is recycled
+/// between calls, so that caller doesn't allocate for concatenation.
+/// 
+#[inline]
+pub fn send_parts(parts: &[&[u8]]) {
+	let _ = internals::try_send_parts(parts);
+}
+
+/// Sends one binary message, made of given `parts`, like [`send_parts`] does,
+/// returning error, when embedder rejects it, like [`try_send_msg_out`] does.
+/// 
+#[inline]
+pub fn try_send_parts(parts: &[&[u8]]) -> Result<(), SendError> {
+	internals::try_send_parts(parts)
+}
+
+/// Sends given binary message to the outside, returning error, when embedder
+/// rejects it.
+/// 
+/// Embedder reports rejections only with `try-send` feature. Without it, this
+/// uses legacy `_3nweb_mp1_send_out_msg` import, and always returns `Ok`.
+/// 
+#[inline]
+pub fn try_send_msg_out(msg: &[u8]) -> Result<(), SendError> {
+	internals::try_send_msg_out(msg)
+}
+
+/// Error of sending message to the outside, reported by embedder as a status
+/// code of `_3nweb_mp1_try_send_out_msg`.
+/// 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+	/// Message is larger than embedder accepts. Status code is `1`.
+	TooLarge,
+	/// Embedder doesn't take messages anymore. Status code is `2`.
+	Closed,
+	/// Embedder's quota for messages is used up. Status code is `3`.
+	OverQuota,
+	/// Embedder has rejected message with other, non-zero status code.
+	Other(u32),
+}
+
+impl SendError {
+
+	/// Status code of message that embedder has taken.
+	/// 
+	pub const STATUS_OK: u32 = 0;
+
+	/// Maps status code from embedder.
+	/// 
+	pub fn from_status(status: u32) -> Result<(), SendError> {
+		match status {
+			Self::STATUS_OK => Ok(()),
+			1 => Err(SendError::TooLarge),
+			2 => Err(SendError::Closed),
+			3 => Err(SendError::OverQuota),
+			code => Err(SendError::Other(code)),
+		}
+	}
+
+	/// Returns status code of this error.
+	/// 
+	pub fn status(self) -> u32 {
+		match self {
+			SendError::TooLarge => 1,
+			SendError::Closed => 2,
+			SendError::OverQuota => 3,
+			SendError::Other(code) => code,
+		}
+	}
+
+}
+
+impl fmt::Display for SendError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SendError::TooLarge => write!(f, "message is too large for embedder"),
+			SendError::Closed => write!(f, "embedder doesn't take messages"),
+			SendError::OverQuota => write!(f, "embedder's quota for messages is used up"),
+			SendError::Other(code) => write!(f, "embedder rejected message with status {code}"),
+		}
+	}
+}
+
+impl core::error::Error for SendError {}
+
+use crate::processor::Processor;
+
+pub use crate::processor::{MsgProcessor, MsgSliceProcessor};
+
 /// Sets a message `processor` function/closure that will be called with binary
-/// messages from the outside.
+/// messages from the outside. Previously set processor is dropped.
 /// 
 /// Messages are given to `processor` as `Vec<u8>` completely separated from
-/// workings of message exchange buffer(s).
+/// workings of message exchange buffer(s). Processor is owned by this module,
+/// hence, it may own its state, like parsers, routers and counters.
+/// 
+#[inline]
+pub fn set_msg_processor(processor: impl FnMut(Vec<u8>) + 'static) {
+	internals::replace_msg_processor(
+		Some(Processor::Owned(Box::new(processor)))
+	);
+}
+
+/// Sets a message `processor` function/closure that will be called with
+/// binary messages from the outside, borrowed from receive buffer. Previously
+/// set processor is dropped.
+/// 
+/// Embedder writes messages into receive buffer, owned by this module, which
+/// is reused for every message and grows only for a message that is longer
+/// than all previous ones. Hence, no allocation happens in a steady state.
+/// Messages that come while processor is running are queued as owned copies.
+/// 
+/// Functions that return processor give this one wrapped as processor of owned
+/// messages.
+/// 
+#[inline]
+pub fn set_msg_slice_processor(processor: impl FnMut(&[u8]) + 'static) {
+	internals::replace_msg_processor(
+		Some(Processor::Slice(Box::new(processor)))
+	);
+}
+
+/// Sets a message `processor` like [`set_msg_processor`] does, returning
+/// previously set processor, if there was one.
+/// 
+/// When called from within running processor, running processor is replaced
+/// after its call returns, and this returns only replacement that has been set
+/// earlier within the same call.
+/// 
+#[inline]
+pub fn replace_msg_processor(
+	processor: impl FnMut(Vec<u8>) + 'static
+) -> Option<MsgProcessor> {
+	internals::replace_msg_processor(
+		Some(Processor::Owned(Box::new(processor)))
+	)
+	.map(Processor::into_owned)
+}
+
+/// Removes message processor, returning it, if it was set. Without processor,
+/// messages from the outside are dropped.
+/// 
+/// When called from within running processor, running processor is removed
+/// after its call returns, and this returns `None`.
 /// 
 #[inline]
-pub fn set_msg_processor(processor: &'static dyn Fn(Vec<u8>) -> ()) -> () {
-	internals::set_msg_processor(processor);
+pub fn take_msg_processor() -> Option<MsgProcessor> {
+	internals::replace_msg_processor(None)
+	.map(Processor::into_owned)
 }
@@ -0,0 +1,291 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! This module provide rust implementation for WASM module to talk with the
+//! outside according to version 2 of 3nweb's message passing api, where
+//! messages go through two ring buffers in WASM's linear memory, so that many
+//! messages can be passed with one call across the boundary.
+//!
+//! Process of this message passing version is following.
+//!
+//! - Embedder locates rings with exported `_3nweb_mp2_rings`, which returns
+//! pointer to four little-endian `u32`: pointer and length of inbound ring's
+//! memory area, followed by pointer and length of outbound ring's area. Layout
+//! of a ring is described in [`ring`] module.
+//!
+//! - To send messages outside, WASM pushes them into outbound ring, and calls
+//! imported `_3nweb_mp2_ring_doorbell`, during which embedder pops all messages
+//! from outbound ring. Doorbell is rung when outbound ring is full, when
+//! [`flush`] is called, and after processing of every inbound batch.
+//!
+//! - To send messages inside, embedder pushes them into inbound ring, and calls
+//! exported `_3nweb_mp2_doorbell`, during which WASM pops all messages from
+//! inbound ring and gives them to processor.
+//!
+//! Message processor behaves in the same way as in `wasm_mp1`.
+//!
+
+pub mod ring;
+
+/// Default capacity of data area in each ring.
+///
+pub const DEFAULT_RING_CAPACITY: usize = 64 * 1024;
+
+pub(crate) mod internals {
+
+	#[cfg(not(feature = "raw-abi"))]
+	use wasm_bindgen::prelude::*;
+	use std::cell::{Cell, RefCell};
+	use crate::processor::{self, Inbound, Processor};
+	use super::ring::{PushError, Ring};
+	use super::DEFAULT_RING_CAPACITY;
+
+	/// Location of rings in memory. Memory is leaked when rings are set up,
+	/// and it is accessed only through views, made for the duration of ring
+	/// operation, as embedder writes into it between calls.
+	///
+	#[derive(Clone, Copy)]
+	struct Rings {
+		in_ptr: *mut u8,
+		in_len: usize,
+		out_ptr: *mut u8,
+		out_len: usize,
+		descriptor: *const u32,
+	}
+
+	thread_local! {
+		static INBOUND: RefCell<Inbound> = const { RefCell::new(Inbound::new()) };
+		static RINGS: Cell<Option<Rings>> = const { Cell::new(None) };
+		static CAPACITIES: Cell<(usize, usize)> = const {
+			Cell::new((DEFAULT_RING_CAPACITY, DEFAULT_RING_CAPACITY))
+		};
+	}
+
+	fn leak_ring_area(capacity: usize) -> (*mut u8, usize) {
+		let area = vec![0u8; Ring::area_len(capacity)].into_boxed_slice();
+		let len = area.len();
+		(Box::leak(area).as_mut_ptr(), len)
+	}
+
+	fn rings() -> Rings {
+		if let Some(rings) = RINGS.with(Cell::get) {
+			return rings;
+		}
+		let (in_capacity, out_capacity) = CAPACITIES.with(Cell::get);
+		let (in_ptr, in_len) = leak_ring_area(in_capacity);
+		let (out_ptr, out_len) = leak_ring_area(out_capacity);
+		let descriptor = Box::leak(Box::new([
+			in_ptr as u32, in_len as u32, out_ptr as u32, out_len as u32
+		]));
+		let rings = Rings {
+			in_ptr, in_len, out_ptr, out_len,
+			descriptor: descriptor.as_ptr(),
+		};
+		RINGS.with(|cell| cell.set(Some(rings)));
+		rings
+	}
+
+	/// Sets capacities of rings, rounded up to powers of two. This has effect
+	/// only before rings are set up, which is indicated by returned value.
+	///
+	pub fn set_ring_capacities(in_capacity: usize, out_capacity: usize) -> bool {
+		if RINGS.with(Cell::get).is_some() {
+			return false;
+		}
+		let round = |capacity: usize| capacity.max(16).next_power_of_two();
+		CAPACITIES.with(|cell| cell.set((round(in_capacity), round(out_capacity))));
+		true
+	}
+
+	fn with_ring<R>(ptr: *mut u8, len: usize, f: impl FnOnce(&mut Ring) -> R) -> R {
+		let area = unsafe { std::slice::from_raw_parts_mut(ptr, len) };
+		let mut ring = Ring::new(area).expect("ring area should be set up");
+		f(&mut ring)
+	}
+
+	/// Applies `f` to inbound ring. View must not outlive the call, as
+	/// embedder writes into ring between calls.
+	///
+	pub fn with_in_ring<R>(f: impl FnOnce(&mut Ring) -> R) -> R {
+		let rings = rings();
+		with_ring(rings.in_ptr, rings.in_len, f)
+	}
+
+	/// Applies `f` to outbound ring. View must not outlive the call, as
+	/// embedder reads from ring between calls.
+	///
+	pub fn with_out_ring<R>(f: impl FnOnce(&mut Ring) -> R) -> R {
+		let rings = rings();
+		with_ring(rings.out_ptr, rings.out_len, f)
+	}
+
+	/// Pushes message into outbound ring, ringing doorbell when ring is full.
+	/// This is implementation.
+	///
+	pub fn send_msg_out(msg: &[u8]) -> Result<(), PushError> {
+		match with_out_ring(|ring| ring.push(msg)) {
+			Err(PushError::Full) => {
+				ring_doorbell();
+				with_out_ring(|ring| ring.push(msg))
+			},
+			result => result,
+		}
+	}
+
+	/// Rings doorbell, if outbound ring has messages. This is implementation.
+	///
+	pub fn flush() {
+		if !with_out_ring(|ring| ring.is_empty()) {
+			ring_doorbell();
+		}
+	}
+
+	fn ring_doorbell() {
+		unsafe {
+			_3nweb_mp2_ring_doorbell();
+		}
+	}
+
+	/// Sets a message `processor`, returning previously set one. This is
+	/// implementation.
+	///
+	pub fn replace_msg_processor(
+		processor: Option<Processor>
+	) -> Option<Processor> {
+		processor::replace_processor(&INBOUND, processor)
+	}
+
+	// On native targets with `testing` feature, embedding is mocked in-process.
+	#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+	use crate::testing::mock_env::_3nweb_mp2_ring_doorbell;
+
+	// This simple classic externing expects to find these functions in `env`
+	// object/namespace imported to WASM by embedding.
+	#[cfg(not(all(feature = "testing", not(target_arch = "wasm32"))))]
+	#[link(wasm_import_module = "env")]
+	extern "C" {
+
+		/// Don't use this directly.
+		/// WASM embedding is expected to provide this function in accordance with
+		/// 3nweb's message passing api, version 2, indicated be `_3nweb_mp2_`
+		/// prefix in the name.
+		///
+		/// This function is called to tell embedding that it should pop all
+		/// messages from outbound ring.
+		///
+		/// Embedder provides this callback in `env` namespace of imports.
+		///
+		fn _3nweb_mp2_ring_doorbell();
+
+	}
+
+	/// Don't use this directly.
+	/// This function is exported from WASM in accordance with 3nweb's message
+	/// passing api, version 2, indicated be `_3nweb_mp2_` prefix in the name.
+	///
+	/// This is called by WASM embedding to locate rings. Returned pointer points
+	/// to four `u32` values: pointer and length of inbound ring's memory area,
+	/// and pointer and length of outbound ring's memory area.
+	///
+	#[cfg_attr(not(feature = "raw-abi"), wasm_bindgen)]
+	#[cfg_attr(feature = "raw-abi", no_mangle)]
+	pub extern "C-unwind" fn _3nweb_mp2_rings() -> usize {
+		rings().descriptor as usize
+	}
+
+	/// Don't use this directly.
+	/// This function is exported from WASM in accordance with 3nweb's message
+	/// passing api, version 2, indicated be `_3nweb_mp2_` prefix in the name.
+	///
+	/// This is called by WASM embedding after it has pushed messages into
+	/// inbound ring. All messages are popped from the ring and given to
+	/// processor, after which outbound ring is flushed.
+	///
+	#[cfg_attr(not(feature = "raw-abi"), wasm_bindgen)]
+	#[cfg_attr(feature = "raw-abi", no_mangle)]
+	pub extern "C-unwind" fn _3nweb_mp2_doorbell() {
+		while let Some(msg) = with_in_ring(|ring| ring.pop()) {
+			processor::process_msg(&INBOUND, msg);
+		}
+		flush();
+	}
+
+}
+
+use crate::processor::Processor;
+
+pub use crate::processor::MsgProcessor;
+pub use ring::PushError;
+
+/// Sets capacities of inbound and outbound rings' data areas, which are
+/// rounded up to powers of two. This should be called before the first use of
+/// this module, and returns `false`, when rings are already set up.
+///
+#[inline]
+pub fn set_ring_capacities(in_capacity: usize, out_capacity: usize) -> bool {
+	internals::set_ring_capacities(in_capacity, out_capacity)
+}
+
+/// Puts given binary message into outbound ring. Message reaches the outside
+/// when doorbell is rung, i.e. when ring gets full, on [`flush`], or after
+/// processing of inbound messages.
+///
+/// Error is returned for message that can't fit into ring, and when ring stays
+/// full after embedder has been asked to pop messages.
+///
+#[inline]
+pub fn send_msg_out(msg: &[u8]) -> Result<(), PushError> {
+	internals::send_msg_out(msg)
+}
+
+/// Rings doorbell for the outside to take messages from outbound ring, if
+/// there are any.
+///
+#[inline]
+pub fn flush() {
+	internals::flush();
+}
+
+/// Sets a message `processor` function/closure that will be called with binary
+/// messages from the outside. Previously set processor is dropped.
+///
+#[inline]
+pub fn set_msg_processor(processor: impl FnMut(Vec<u8>) + 'static) {
+	internals::replace_msg_processor(
+		Some(Processor::Owned(Box::new(processor)))
+	);
+}
+
+/// Sets a message `processor` like [`set_msg_processor`] does, returning
+/// previously set processor, if there was one.
+///
+#[inline]
+pub fn replace_msg_processor(
+	processor: impl FnMut(Vec<u8>) + 'static
+) -> Option<MsgProcessor> {
+	internals::replace_msg_processor(
+		Some(Processor::Owned(Box::new(processor)))
+	)
+	.map(Processor::into_owned)
+}
+
+/// Removes message processor, returning it, if it was set. Without processor,
+/// messages from the outside are dropped.
+///
+#[inline]
+pub fn take_msg_processor() -> Option<MsgProcessor> {
+	internals::replace_msg_processor(None)
+	.map(Processor::into_owned)
+}
@@ -0,0 +1,198 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Ring buffer of messages in linear memory, shared by WASM and embedder.
+//!
+//! Ring's memory area starts with a header of two little-endian `u32`
+//! positions: head, which only consumer advances, and tail, which only
+//! producer advances. Positions run freely, wrapping at `u32` bounds, and
+//! their difference is number of used bytes. Data area follows the header,
+//! and its length is a power of two.
+//!
+//! Every message is a record of little-endian `u32` length, followed by
+//! message bytes. Records wrap around the end of data area.
+//!
+
+use std::fmt;
+
+/// Length of ring's header with head and tail positions.
+///
+pub const HEADER_LEN: usize = 8;
+
+/// Length of record's header with message length.
+///
+pub const RECORD_HEADER_LEN: usize = 4;
+
+/// Error of pushing a message into ring.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushError {
+	/// Ring has no space for the message, till consumer pops some.
+	Full,
+	/// Message can't fit into ring even when it is empty.
+	TooLarge,
+}
+
+impl fmt::Display for PushError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PushError::Full => write!(f, "ring buffer is full"),
+			PushError::TooLarge => write!(f, "message is too large for ring buffer"),
+		}
+	}
+}
+
+impl std::error::Error for PushError {}
+
+/// View of a ring over its memory area.
+///
+pub struct Ring<'a> {
+	area: &'a mut [u8],
+}
+
+impl<'a> Ring<'a> {
+
+	/// Makes view of a ring, which memory `area` includes header. Returns
+	/// `None`, if data area's length isn't a power of two.
+	///
+	pub fn new(area: &'a mut [u8]) -> Option<Self> {
+		let capacity = area.len().checked_sub(HEADER_LEN)?;
+		if capacity.is_power_of_two() && (capacity <= (u32::MAX as usize)) {
+			Some(Ring { area })
+		} else {
+			None
+		}
+	}
+
+	/// Returns length of ring's memory area, including header, for given
+	/// capacity of data area.
+	///
+	pub fn area_len(capacity: usize) -> usize {
+		HEADER_LEN + capacity
+	}
+
+	/// Returns length of data area.
+	///
+	pub fn capacity(&self) -> usize {
+		self.area.len() - HEADER_LEN
+	}
+
+	/// Returns maximum length of a message that can be pushed into empty ring.
+	///
+	pub fn max_msg_len(&self) -> usize {
+		self.capacity() - RECORD_HEADER_LEN
+	}
+
+	fn read_u32(&self, offset: usize) -> u32 {
+		let mut bytes = [0u8; 4];
+		bytes.copy_from_slice(&self.area[offset..(offset + 4)]);
+		u32::from_le_bytes(bytes)
+	}
+
+	fn write_u32(&mut self, offset: usize, value: u32) {
+		self.area[offset..(offset + 4)].copy_from_slice(&value.to_le_bytes());
+	}
+
+	fn head(&self) -> u32 {
+		self.read_u32(0)
+	}
+
+	fn tail(&self) -> u32 {
+		self.read_u32(4)
+	}
+
+	/// Returns number of bytes, used by records in the ring.
+	///
+	pub fn used(&self) -> usize {
+		self.tail().wrapping_sub(self.head()) as usize
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.used() == 0
+	}
+
+	/// Makes ring empty, dropping all records. This is for setting up ring,
+	/// and for recovering from inconsistent positions.
+	///
+	pub fn reset(&mut self) {
+		self.write_u32(0, 0);
+		self.write_u32(4, 0);
+	}
+
+	fn copy_in(&mut self, pos: u32, bytes: &[u8]) {
+		let capacity = self.capacity();
+		let start = (pos as usize) & (capacity - 1);
+		let first = bytes.len().min(capacity - start);
+		let data = &mut self.area[HEADER_LEN..];
+		data[start..(start + first)].copy_from_slice(&bytes[..first]);
+		data[..(bytes.len() - first)].copy_from_slice(&bytes[first..]);
+	}
+
+	fn copy_out(&self, pos: u32, bytes: &mut [u8]) {
+		let capacity = self.capacity();
+		let start = (pos as usize) & (capacity - 1);
+		let first = bytes.len().min(capacity - start);
+		let data = &self.area[HEADER_LEN..];
+		bytes[..first].copy_from_slice(&data[start..(start + first)]);
+		let rest = bytes.len() - first;
+		bytes[first..].copy_from_slice(&data[..rest]);
+	}
+
+	/// Pushes message into the ring. This is done by producer.
+	///
+	pub fn push(&mut self, msg: &[u8]) -> Result<(), PushError> {
+		if msg.len() > self.max_msg_len() {
+			return Err(PushError::TooLarge);
+		}
+		let record_len = RECORD_HEADER_LEN + msg.len();
+		if (self.capacity() - self.used().min(self.capacity())) < record_len {
+			return Err(PushError::Full);
+		}
+		let tail = self.tail();
+		self.copy_in(tail, &(msg.len() as u32).to_le_bytes());
+		self.copy_in(tail.wrapping_add(RECORD_HEADER_LEN as u32), msg);
+		self.write_u32(4, tail.wrapping_add(record_len as u32));
+		Ok(())
+	}
+
+	/// Pops message from the ring. This is done by consumer.
+	///
+	/// When positions or record are inconsistent, i.e. other side has messed up
+	/// the ring, ring is reset, and `None` is returned.
+	///
+	pub fn pop(&mut self) -> Option<Vec<u8>> {
+		let used = self.used();
+		if used == 0 {
+			return None;
+		}
+		if (used > self.capacity()) || (used < RECORD_HEADER_LEN) {
+			self.reset();
+			return None;
+		}
+		let head = self.head();
+		let mut len_bytes = [0u8; RECORD_HEADER_LEN];
+		self.copy_out(head, &mut len_bytes);
+		let len = u32::from_le_bytes(len_bytes) as usize;
+		if (used - RECORD_HEADER_LEN) < len {
+			self.reset();
+			return None;
+		}
+		let mut msg = vec![0u8; len];
+		self.copy_out(head.wrapping_add(RECORD_HEADER_LEN as u32), &mut msg);
+		self.write_u32(0, head.wrapping_add((RECORD_HEADER_LEN + len) as u32));
+		Some(msg)
+	}
+
+}
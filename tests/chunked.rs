@@ -0,0 +1,231 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Streams of `chunked` on mocked embedding: chunking of written bytes, end
+//! and abort of incoming streams, and cancels in both directions.
+
+use std::io::{ErrorKind, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use futures_core::Stream;
+use wasm_message_passing_3nweb::chunked::{
+	self, Chunk, Frame, FrameKind, InStream, IncomingStreams, StreamError
+};
+use wasm_message_passing_3nweb::testing;
+
+fn take_frames() -> Vec<Frame> {
+	testing::take_sent_msgs().into_iter()
+	.map(|msg| Frame::decode(msg).unwrap())
+	.collect()
+}
+
+fn frame(kind: FrameKind, stream_id: u32, body: &[u8]) -> Frame {
+	Frame { kind, stream_id, body: body.to_vec() }
+}
+
+fn inject_frame(kind: FrameKind, stream_id: u32, body: &[u8]) {
+	testing::inject_msg(frame(kind, stream_id, body).encode());
+}
+
+fn accept(streams: &mut IncomingStreams, waker: &Waker) -> InStream {
+	match Pin::new(streams).poll_next(&mut Context::from_waker(waker)) {
+		Poll::Ready(Some(stream)) => stream,
+		_ => panic!("incoming stream should be accepted"),
+	}
+}
+
+fn poll_chunk(stream: &mut InStream) -> Poll<Option<Result<Chunk, StreamError>>> {
+	Pin::new(stream).poll_next(&mut Context::from_waker(Waker::noop()))
+}
+
+#[test]
+fn written_bytes_are_sent_in_chunks() {
+	let mut stream = chunked::open_out_stream_with_chunk_size(4);
+	let id = stream.stream_id();
+	stream.write_all(b"0123456789").unwrap();
+	assert_eq!(take_frames(), [
+		frame(FrameKind::Chunk, id, b"0123"),
+		frame(FrameKind::Chunk, id, b"4567"),
+	]);
+	stream.finish().unwrap();
+	assert_eq!(take_frames(), [
+		frame(FrameKind::Chunk, id, b"89"),
+		frame(FrameKind::End, id, b""),
+	]);
+}
+
+#[test]
+fn dropped_out_stream_is_aborted() {
+	let mut stream = chunked::open_out_stream_with_chunk_size(4);
+	let id = stream.stream_id();
+	stream.write_all(b"01").unwrap();
+	drop(stream);
+	assert_eq!(take_frames(), [frame(FrameKind::Abort, id, b"")]);
+}
+
+// sends are rejected only with imports, that return status
+#[cfg(any(feature = "try-send", feature = "send-parts"))]
+#[test]
+fn failed_write_takes_no_bytes() {
+	use wasm_message_passing_3nweb::wasm_mp1::SendError;
+	let mut stream = chunked::open_out_stream_with_chunk_size(4);
+	let id = stream.stream_id();
+	stream.write_all(b"01").unwrap();
+	testing::reject_sent_msgs(Some(SendError::OverQuota));
+	assert!(stream.write(b"2345").is_err());
+	testing::reject_sent_msgs(None);
+	stream.write_all(b"2345").unwrap();
+	stream.finish().unwrap();
+	assert_eq!(take_frames(), [
+		frame(FrameKind::Chunk, id, b"0123"),
+		frame(FrameKind::Chunk, id, b"45"),
+		frame(FrameKind::End, id, b""),
+	]);
+}
+
+#[test]
+fn incoming_stream_ends_or_is_aborted() {
+	let mut streams = chunked::install(1024);
+	inject_frame(FrameKind::Chunk, 7, b"abc");
+	inject_frame(FrameKind::Chunk, 7, b"de");
+	inject_frame(FrameKind::End, 7, b"");
+	inject_frame(FrameKind::Chunk, 8, b"xyz");
+	inject_frame(FrameKind::Abort, 8, b"gave up");
+
+	let mut ended = accept(&mut streams, Waker::noop());
+	assert_eq!(ended.stream_id(), 7);
+	assert_eq!(poll_chunk(&mut ended), Poll::Ready(Some(Ok(b"abc".to_vec()))));
+	assert_eq!(poll_chunk(&mut ended), Poll::Ready(Some(Ok(b"de".to_vec()))));
+	assert_eq!(poll_chunk(&mut ended), Poll::Ready(None));
+
+	let mut aborted = accept(&mut streams, Waker::noop());
+	assert_eq!(aborted.stream_id(), 8);
+	assert_eq!(poll_chunk(&mut aborted), Poll::Ready(Some(Ok(b"xyz".to_vec()))));
+	assert_eq!(
+		poll_chunk(&mut aborted),
+		Poll::Ready(Some(Err(StreamError::Aborted(b"gave up".to_vec()))))
+	);
+	assert_eq!(poll_chunk(&mut aborted), Poll::Ready(None));
+	drop((ended, aborted));
+	assert!(take_frames().is_empty());
+}
+
+#[test]
+fn cancel_from_outside_aborts_out_stream() {
+	let _streams = chunked::install(1024);
+	let mut stream = chunked::open_out_stream_with_chunk_size(4);
+	let id = stream.stream_id();
+	inject_frame(FrameKind::Cancel, id, b"");
+	assert_eq!(take_frames(), [frame(FrameKind::Abort, id, b"")]);
+	assert_eq!(stream.write(b"0123").unwrap_err().kind(), ErrorKind::ConnectionAborted);
+	drop(stream);
+	assert!(take_frames().is_empty());
+}
+
+#[test]
+fn stream_over_in_flight_limit_is_cancelled() {
+	let mut streams = chunked::install(8);
+	inject_frame(FrameKind::Chunk, 3, b"01234");
+	inject_frame(FrameKind::Chunk, 3, b"56789");
+	assert_eq!(take_frames(), [frame(FrameKind::Cancel, 3, b"")]);
+	// frames of cancelled stream are ignored, till the outside aborts it
+	inject_frame(FrameKind::Chunk, 3, b"a");
+	inject_frame(FrameKind::Abort, 3, b"");
+
+	let mut stream = accept(&mut streams, Waker::noop());
+	assert_eq!(poll_chunk(&mut stream), Poll::Ready(Some(Err(StreamError::Overflow))));
+	assert_eq!(poll_chunk(&mut stream), Poll::Ready(None));
+	drop(stream);
+	assert!(take_frames().is_empty());
+
+	// in-flight bytes of cancelled stream are freed
+	inject_frame(FrameKind::Chunk, 4, b"01234567");
+	let mut stream = accept(&mut streams, Waker::noop());
+	assert_eq!(poll_chunk(&mut stream), Poll::Ready(Some(Ok(b"01234567".to_vec()))));
+}
+
+#[test]
+fn dropped_in_stream_is_cancelled() {
+	let mut streams = chunked::install(1024);
+	inject_frame(FrameKind::Chunk, 5, b"abc");
+	drop(accept(&mut streams, Waker::noop()));
+	assert_eq!(take_frames(), [frame(FrameKind::Cancel, 5, b"")]);
+}
+
+/// Waker that opens and finishes stream, when woken.
+#[derive(Default)]
+struct StreamOpeningWaker(AtomicUsize);
+
+impl Wake for StreamOpeningWaker {
+	fn wake(self: Arc<Self>) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+		chunked::open_out_stream().finish().unwrap();
+	}
+}
+
+#[test]
+fn waker_of_accepting_may_use_streams() {
+	let mut streams = chunked::install(1024);
+	let counter = Arc::new(StreamOpeningWaker::default());
+	let waker = Waker::from(counter.clone());
+	assert!(Pin::new(&mut streams).poll_next(&mut Context::from_waker(&waker)).is_pending());
+	inject_frame(FrameKind::Chunk, 1, b"abc");
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+	let frames = take_frames();
+	assert_eq!(frames.len(), 1);
+	assert_eq!(frames[0].kind, FrameKind::End);
+	assert_eq!(accept(&mut streams, &waker).stream_id(), 1);
+}
+
+/// Waker that counts wakes.
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+	fn wake(self: Arc<Self>) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+#[test]
+fn install_closes_previous_streams() {
+	let counter = Arc::new(CountingWaker::default());
+	let waker = Waker::from(counter.clone());
+	let mut cx = Context::from_waker(&waker);
+	let mut old_streams = chunked::install(1024);
+	inject_frame(FrameKind::Chunk, 1, b"abc");
+	let mut stream = accept(&mut old_streams, Waker::noop());
+	assert_eq!(poll_chunk(&mut stream), Poll::Ready(Some(Ok(b"abc".to_vec()))));
+	assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+	assert!(Pin::new(&mut old_streams).poll_next(&mut cx).is_pending());
+	inject_frame(FrameKind::Chunk, 2, b"not accepted");
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+	let mut streams = chunked::install(1024);
+	assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+	assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(Some(Err(StreamError::Closed))));
+	assert_eq!(Pin::new(&mut stream).poll_next(&mut cx), Poll::Ready(None));
+	assert!(matches!(Pin::new(&mut old_streams).poll_next(&mut cx), Poll::Ready(None)));
+	// streams of previous installation don't reach new one
+	assert!(Pin::new(&mut streams).poll_next(&mut cx).is_pending());
+
+	let _new_streams = chunked::install(1024);
+	assert_eq!(counter.0.load(Ordering::SeqCst), 3);
+	assert!(matches!(Pin::new(&mut streams).poll_next(&mut cx), Poll::Ready(None)));
+	drop((stream, old_streams, streams));
+	assert!(take_frames().is_empty());
+}
@@ -0,0 +1,128 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! WASM modules in text format, that go through handshakes of message
+//! passing, version 1, with embedders in `host`, send messages out, and tell
+//! versions of message passing.
+
+/// Echoes every incoming message, reading it with write-into callback.
+pub const WRITE_INTO_ECHO_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp1_send_out_msg" (func $send (param i32 i32)))
+	(import "env" "_3nweb_mp1_write_msg_into" (func $write (param i32)))
+	(memory (export "memory") 1)
+	(func (export "_3nweb_mp1_accept_msg") (param $len i32)
+		(call $write (i32.const 1024))
+		(call $send (i32.const 1024) (local.get $len))))"#;
+
+/// Echoes every incoming message, giving out buffer for it.
+pub const GET_BUFFER_ECHO_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp1_send_out_msg" (func $send (param i32 i32)))
+	(memory (export "memory") 1)
+	(func (export "_3nweb_mp1_get_buffer") (param $len i32) (result i32)
+		(i32.const 2048))
+	(func (export "_3nweb_mp1_accept_msg") (param $len i32)
+		(call $send (i32.const 2048) (local.get $len))))"#;
+
+/// Refuses every incoming message, giving out zero pointer for it.
+pub const REFUSING_WAT: &str = r#"(module
+	(memory (export "memory") 1)
+	(func (export "_3nweb_mp1_get_buffer") (param $len i32) (result i32)
+		(i32.const 0))
+	(func (export "_3nweb_mp1_accept_msg") (param $len i32)
+		unreachable))"#;
+
+/// Echoes incoming messages up to 16 bytes, reading them with write-into
+/// callback, and refuses longer ones with refusal callback.
+pub const SIGNALLED_REFUSAL_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp1_send_out_msg" (func $send (param i32 i32)))
+	(import "env" "_3nweb_mp1_write_msg_into" (func $write (param i32)))
+	(import "env" "_3nweb_mp1_refuse_msg" (func $refuse (param i32)))
+	(memory (export "memory") 1)
+	(func (export "_3nweb_mp1_accept_msg") (param $len i32)
+		(if (i32.gt_u (local.get $len) (i32.const 16))
+			(then (call $refuse (local.get $len)))
+			(else
+				(call $write (i32.const 1024))
+				(call $send (i32.const 1024) (local.get $len))))))"#;
+
+/// Takes no incoming message, as older modules do with refused ones.
+pub const NOT_TAKING_WAT: &str = r#"(module
+	(memory (export "memory") 1)
+	(func (export "_3nweb_mp1_accept_msg") (param $len i32)))"#;
+
+/// Reports panic at `src/lib.rs:7:3` with message "boom", and traps.
+pub const PANICKING_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp1_panic" (func $panic (param i32 i32)))
+	(memory (export "memory") 1)
+	(data (i32.const 64) "\07\00\00\00\03\00\00\00\0a\00\00\00src/lib.rsboom")
+	(func (export "_3nweb_mp1_get_buffer") (param $len i32) (result i32)
+		(i32.const 2048))
+	(func (export "_3nweb_mp1_accept_msg") (param $len i32)
+		(call $panic (i32.const 64) (i32.const 26))
+		unreachable))"#;
+
+/// Sends message of given length from bytes at 1024, returning status, with
+/// exported `try_send`, and with legacy import, that has no status, with
+/// exported `send`.
+pub const SENDING_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp1_try_send_out_msg"
+		(func $try_send (param i32 i32) (result i32)))
+	(import "env" "_3nweb_mp1_send_out_msg" (func $send (param i32 i32)))
+	(memory (export "memory") 1)
+	(data (i32.const 1024) "0123456789abcdef")
+	(func (export "try_send") (param $len i32) (result i32)
+		(call $try_send (i32.const 1024) (local.get $len)))
+	(func (export "send") (param $len i32)
+		(call $send (i32.const 1024) (local.get $len))))"#;
+
+/// Sends message, made of parts, which pointers and lengths are at given
+/// pointer, with exported `send_parts`, returning status. Parts at 64 are
+/// "hello", "" and " world". Malformed parts are at 128, with pointer out of
+/// memory bounds, and at 136, with length out of memory bounds.
+pub const SENDING_PARTS_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp1_send_out_msg_parts"
+		(func $send_parts (param i32 i32) (result i32)))
+	(memory (export "memory") 1)
+	(data (i32.const 1024) "hello world")
+	(data (i32.const 64)
+		"\00\04\00\00\05\00\00\00\00\08\00\00\00\00\00\00\05\04\00\00\06\00\00\00")
+	(data (i32.const 128) "\00\00\01\00\01\00\00\00\00\04\00\00\ff\ff\ff\ff")
+	(func (export "send_parts") (param $parts_ptr i32) (param $num i32)
+		(result i32)
+		(call $send_parts (local.get $parts_ptr) (local.get $num))))"#;
+
+/// Returns pointer to versions descriptor, set with exported `set_ptr`. Valid
+/// descriptor with versions 1 and 2, maximum message size 1000, and
+/// capabilities 5 is at 64. Descriptor with too many versions is at 128, and
+/// descriptor, cut by the end of memory, is at 65528.
+pub const VERSIONS_WAT: &str = r#"(module
+	(memory (export "memory") 1)
+	(global $ptr (mut i32) (i32.const 64))
+	(data (i32.const 64)
+		"\02\00\00\00\01\00\00\00\02\00\00\00\e8\03\00\00\05\00\00\00")
+	(data (i32.const 128) "\41\00\00\00")
+	(data (i32.const 65528) "\02\00\00\00\01\00\00\00")
+	(func (export "_3nweb_mp_versions") (result i32)
+		(global.get $ptr))
+	(func (export "set_ptr") (param $ptr i32)
+		(global.set $ptr (local.get $ptr))))"#;
+
+/// Exports functions of message passing, version 2, without versions
+/// descriptor.
+pub const MP2_EXPORTS_WAT: &str = r#"(module
+	(memory (export "memory") 1)
+	(func (export "_3nweb_mp2_rings") (result i32)
+		(i32.const 0))
+	(func (export "_3nweb_mp2_doorbell")))"#;
@@ -0,0 +1,62 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Conformance harness passes reference guest on mocked embedding, and
+//! reports deviations of embedding, that loses and reorders messages.
+
+use wasm_message_passing_3nweb::conformance::harness::{
+	self, HostUnderTest, MockHost
+};
+
+#[test]
+fn mocked_embedding_conforms() {
+	let report = harness::run(&mut MockHost::start());
+	assert!(report.is_conformant(), "{report}");
+	assert!(report.skipped.is_empty(), "{report}");
+}
+
+/// Embedding that drops empty messages from guest, and gives the rest in
+/// reverse order.
+struct Sloppy(MockHost);
+
+impl HostUnderTest for Sloppy {
+	type Error = &'static str;
+
+	fn send(&mut self, msg: Vec<u8>) -> Result<(), Self::Error> {
+		self.0.send(msg)
+	}
+
+	fn take_out_msgs(&mut self) -> Vec<Vec<u8>> {
+		let mut msgs = self.0.take_out_msgs();
+		msgs.retain(|msg| !msg.is_empty());
+		msgs.reverse();
+		msgs
+	}
+}
+
+#[test]
+fn deviations_are_reported() {
+	let report = harness::run(&mut Sloppy(MockHost::start()));
+	let deviating = report.deviations.iter()
+	.map(|deviation| deviation.check)
+	.collect::<Vec<_>>();
+	assert_eq!(deviating, [
+		"zero-length message",
+		"ordering of burst",
+		"ordering of sends into guest",
+		"sends within accept call",
+	]);
+	assert!(report.passed.contains(&"echo"));
+}
@@ -0,0 +1,86 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Embeddings for both engines pass conformance harness with reference guest,
+//! built from example `conformance_guest` into WASM module with
+//!
+//! ```sh
+//! cargo build --release --example conformance_guest \
+//!   --target wasm32-unknown-unknown \
+//!   --no-default-features --features std,raw-abi,conformance
+//! ```
+//!
+//! as CI does before tests. Other location of module is given in environment
+//! variable `CONFORMANCE_GUEST_WASM`. Without module, tests are skipped.
+
+use std::path::PathBuf;
+use wasm_message_passing_3nweb::conformance::harness;
+use wasm_message_passing_3nweb::host::{
+	Mp1Ctx, wasmi as mp1_wasmi, wasmtime as mp1_wasmtime
+};
+
+fn guest_wasm() -> Option<Vec<u8>> {
+	let wasm_file = match std::env::var_os("CONFORMANCE_GUEST_WASM") {
+		Some(wasm_file) => PathBuf::from(wasm_file),
+		None => PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+		.join("target").join("wasm32-unknown-unknown").join("release")
+		.join("examples").join("conformance_guest.wasm"),
+	};
+	let wasm = std::fs::read(&wasm_file).ok();
+	if wasm.is_none() {
+		eprintln!(
+			"skipping: reference guest isn't built at {}", wasm_file.display()
+		);
+	}
+	wasm
+}
+
+#[test]
+fn wasmi_embedding_conforms() {
+	use wasmi::{Engine, Linker, Module, Store};
+	let Some(wasm) = guest_wasm() else { return; };
+	let engine = Engine::default();
+	let module = Module::new(&engine, &wasm).unwrap();
+	let (ctx, out_msgs) = Mp1Ctx::with_channel();
+	let mut store = Store::new(&engine, ctx);
+	let mut linker = Linker::new(&engine);
+	mp1_wasmi::add_to_linker(&mut linker).unwrap();
+	let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+	let mut host = mp1_wasmi::ConformanceHost::start(
+		store, instance, out_msgs
+	).unwrap();
+	let report = harness::run(&mut host);
+	assert!(report.is_conformant(), "{report}");
+	assert!(report.skipped.is_empty(), "{report}");
+}
+
+#[test]
+fn wasmtime_embedding_conforms() {
+	use wasmtime::{Engine, Linker, Module, Store};
+	let Some(wasm) = guest_wasm() else { return; };
+	let engine = Engine::default();
+	let module = Module::new(&engine, &wasm).unwrap();
+	let (ctx, out_msgs) = Mp1Ctx::with_channel();
+	let mut store = Store::new(&engine, ctx);
+	let mut linker = Linker::new(&engine);
+	mp1_wasmtime::add_to_linker(&mut linker).unwrap();
+	let instance = linker.instantiate(&mut store, &module).unwrap();
+	let mut host = mp1_wasmtime::ConformanceHost::start(
+		store, instance, out_msgs
+	).unwrap();
+	let report = harness::run(&mut host);
+	assert!(report.is_conformant(), "{report}");
+	assert!(report.skipped.is_empty(), "{report}");
+}
@@ -0,0 +1,134 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Credit-based flow control of `flow` on mocked embedding: grants of
+//! credits, their consumption by sends, and queueing of waiting sends.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use wasm_message_passing_3nweb::flow::{self, FlowError, Frame};
+use wasm_message_passing_3nweb::testing;
+use wasm_message_passing_3nweb::wasm_mp1::SendError;
+
+/// Waker that counts its wakes.
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+	fn wake(self: Arc<Self>) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+fn poll<F: Future + Unpin>(fut: &mut F, waker: &Waker) -> Poll<F::Output> {
+	Pin::new(fut).poll(&mut Context::from_waker(waker))
+}
+
+fn take_frames() -> Vec<Frame> {
+	testing::take_sent_msgs().into_iter()
+	.map(|msg| Frame::decode(msg).unwrap())
+	.collect()
+}
+
+fn inject_frame(frame: Frame) {
+	testing::inject_msg(frame.encode());
+}
+
+fn install_collecting(window: u32) -> Rc<RefCell<Vec<Vec<u8>>>> {
+	let received = Rc::new(RefCell::new(Vec::new()));
+	let sink = received.clone();
+	flow::install(window, move |msg| sink.borrow_mut().push(msg));
+	received
+}
+
+#[test]
+fn processed_msgs_are_granted_back_in_batches() {
+	let received = install_collecting(4);
+	assert_eq!(take_frames(), [Frame::Credit(4)]);
+	inject_frame(Frame::Data(b"a".to_vec()));
+	assert!(take_frames().is_empty());
+	inject_frame(Frame::Data(b"b".to_vec()));
+	assert_eq!(take_frames(), [Frame::Credit(2)]);
+	assert_eq!(received.take(), [b"a".to_vec(), b"b".to_vec()]);
+	assert_eq!(flow::overrun_count(), 0);
+}
+
+#[test]
+fn sends_consume_granted_credits() {
+	install_collecting(4);
+	take_frames();
+	assert_eq!(flow::try_send(b"x"), Err(FlowError::WouldBlock));
+	inject_frame(Frame::Credit(2));
+	assert_eq!(flow::send_credits(), 2);
+	assert_eq!(flow::try_send(b"x"), Ok(()));
+	assert_eq!(flow::try_send(b"y"), Ok(()));
+	assert_eq!(flow::try_send(b"z"), Err(FlowError::WouldBlock));
+	assert_eq!(flow::send_credits(), 0);
+	assert_eq!(take_frames(), [Frame::Data(b"x".to_vec()), Frame::Data(b"y".to_vec())]);
+}
+
+#[test]
+fn waiting_sends_go_in_order() {
+	install_collecting(4);
+	take_frames();
+	let counter = Arc::new(CountingWaker::default());
+	let waker = Waker::from(counter.clone());
+	let mut first = flow::send(b"1".to_vec());
+	let mut second = flow::send(b"2".to_vec());
+	assert_eq!(poll(&mut first, &waker), Poll::Pending);
+	assert_eq!(poll(&mut second, &waker), Poll::Pending);
+
+	inject_frame(Frame::Credit(1));
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+	// waiting sends go before new ones
+	assert_eq!(flow::try_send(b"3"), Err(FlowError::WouldBlock));
+	assert_eq!(poll(&mut second, &waker), Poll::Pending);
+	assert_eq!(poll(&mut first, &waker), Poll::Ready(Ok(())));
+
+	inject_frame(Frame::Credit(1));
+	assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+	assert_eq!(poll(&mut second, &waker), Poll::Ready(Ok(())));
+	assert_eq!(take_frames(), [Frame::Data(b"1".to_vec()), Frame::Data(b"2".to_vec())]);
+}
+
+#[test]
+fn install_starts_flow_anew() {
+	install_collecting(2);
+	let counter = Arc::new(CountingWaker::default());
+	let waker = Waker::from(counter.clone());
+	let mut first = flow::send(b"1".to_vec());
+	assert_eq!(poll(&mut first, &waker), Poll::Pending);
+	inject_frame(Frame::Credit(3));
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+	let mut second = flow::send(b"2".to_vec());
+	assert_eq!(poll(&mut second, &waker), Poll::Pending);
+	take_frames();
+
+	let received = install_collecting(2);
+	assert_eq!(take_frames(), [Frame::Credit(2)]);
+	assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+	assert_eq!(poll(&mut first, &waker), Poll::Ready(Err(SendError::Closed)));
+	assert_eq!(poll(&mut second, &waker), Poll::Ready(Err(SendError::Closed)));
+	assert_eq!(flow::send_credits(), 0);
+	assert_eq!(flow::try_send(b"3"), Err(FlowError::WouldBlock));
+	inject_frame(Frame::Data(b"a".to_vec()));
+	assert_eq!(received.take(), [b"a".to_vec()]);
+	assert_eq!(take_frames(), [Frame::Credit(1)]);
+}
@@ -0,0 +1,99 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Log records of `logging` on mocked embedding: encoding of records, and
+//! forwarding of guest's `log` and `tracing` records to embedder.
+
+use wasm_message_passing_3nweb::logging::{self, LogLevel, LogRecord};
+use wasm_message_passing_3nweb::{mux, testing};
+
+const LOG_CHANNEL: u32 = 9;
+
+fn record() -> LogRecord {
+	LogRecord {
+		level: LogLevel::Warn,
+		target: "guest::module".to_string(),
+		message: "disk is ½ full".to_string(),
+		key_values: vec![
+			("free".to_string(), "512".to_string()),
+			("unit".to_string(), String::new()),
+		],
+	}
+}
+
+#[test]
+fn sent_record_is_decoded_from_msg() {
+	logging::send_record(LOG_CHANNEL, &record()).unwrap();
+	let sent = testing::take_sent_msgs();
+	assert_eq!(sent.len(), 1);
+	assert_eq!(logging::decode_msg(LOG_CHANNEL, &sent[0]), Some(record()));
+	assert_eq!(logging::decode_msg(LOG_CHANNEL + 1, &sent[0]), None);
+	assert_eq!(LogRecord::decode(&record().encode()), Some(record()));
+}
+
+#[test]
+fn malformed_records_are_not_decoded() {
+	let encoded = record().encode();
+	let truncated = mux::encode(LOG_CHANNEL, &encoded[..(encoded.len() - 1)]);
+	assert_eq!(logging::decode_msg(LOG_CHANNEL, &truncated), None);
+	let bad_level = mux::encode(LOG_CHANNEL, &[0, 0, 0, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(logging::decode_msg(LOG_CHANNEL, &bad_level), None);
+	assert_eq!(logging::decode_msg(LOG_CHANNEL, &[9, 0]), None);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_records_reach_embedder() {
+	logging::init_log(LOG_CHANNEL, log::LevelFilter::Info).unwrap();
+	log::debug!("filtered out");
+	log::warn!(target: "guest::module", free = 512, unit = ""; "disk is ½ full");
+	let sent = testing::take_sent_msgs();
+	assert_eq!(sent.len(), 1);
+	assert_eq!(logging::decode_msg(LOG_CHANNEL, &sent[0]), Some(record()));
+}
+
+/// Subscriber, that enables everything and records nothing, under layer.
+#[cfg(feature = "tracing")]
+struct EnablingSubscriber;
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for EnablingSubscriber {
+	fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+		true
+	}
+	fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+		tracing::span::Id::from_u64(1)
+	}
+	fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+	fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+	fn event(&self, _: &tracing::Event<'_>) {}
+	fn enter(&self, _: &tracing::span::Id) {}
+	fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_events_reach_embedder() {
+	use tracing_subscriber::Layer;
+
+	let subscriber = logging::Mp1Layer::new(LOG_CHANNEL)
+	.with_subscriber(EnablingSubscriber);
+	tracing::subscriber::with_default(subscriber, || {
+		tracing::warn!(target: "guest::module", free = 512, unit = "", "disk is ½ full");
+	});
+	let sent = testing::take_sent_msgs();
+	assert_eq!(sent.len(), 1);
+	assert_eq!(logging::decode_msg(LOG_CHANNEL, &sent[0]), Some(record()));
+}
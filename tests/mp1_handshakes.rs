@@ -0,0 +1,275 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Both handshakes of passing messages into WASM, version 1: with callback
+//! `_3nweb_mp1_write_msg_into`, and with exported `_3nweb_mp1_get_buffer`,
+//! including refusal of messages above maximum size, and report of panic,
+//! that happens during processing. Embedder's rejections of messages from WASM
+//! are reported with status, or are silent with legacy import.
+//! Message parts are gathered into one message, and malformed parts trap.
+//! Versions are read from descriptor, or are probed from exports without it.
+
+mod common;
+
+use std::sync::mpsc::Receiver;
+use wasm_message_passing_3nweb::host::{Mp1Ctx, wasmi as mp1_wasmi};
+use wasm_message_passing_3nweb::mp_versions::{Capabilities, MP1, MP2, MpVersions};
+use wasm_message_passing_3nweb::wasm_mp1::SendError;
+use wasmi::{Engine, Instance, Linker, Module, Store};
+use common::{
+	GET_BUFFER_ECHO_WAT, MP2_EXPORTS_WAT, NOT_TAKING_WAT, PANICKING_WAT,
+	REFUSING_WAT, SENDING_PARTS_WAT, SENDING_WAT, SIGNALLED_REFUSAL_WAT,
+	VERSIONS_WAT, WRITE_INTO_ECHO_WAT
+};
+
+fn instantiate(wat: &str) -> (Store<Mp1Ctx>, Instance, Receiver<Vec<u8>>) {
+	let engine = Engine::default();
+	let module = Module::new(&engine, wat::parse_str(wat).unwrap()).unwrap();
+	let (ctx, out_msgs) = Mp1Ctx::with_channel();
+	let mut store = Store::new(&engine, ctx);
+	let mut linker = Linker::new(&engine);
+	mp1_wasmi::add_to_linker(&mut linker).unwrap();
+	let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+	(store, instance, out_msgs)
+}
+
+fn assert_echo(wat: &str) {
+	let (mut store, instance, out_msgs) = instantiate(wat);
+	let msgs = [b"first".to_vec(), Vec::new(), vec![7u8; 1000]];
+	for msg in msgs.iter() {
+		mp1_wasmi::send_into(&mut store, &instance, msg.clone()).unwrap();
+	}
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), msgs);
+}
+
+#[test]
+fn host_passes_msgs_with_write_into_callback() {
+	assert_echo(WRITE_INTO_ECHO_WAT);
+}
+
+#[test]
+fn host_passes_msgs_with_get_buffer() {
+	assert_echo(GET_BUFFER_ECHO_WAT);
+}
+
+#[test]
+fn host_reports_refused_msgs() {
+	let (mut store, instance, _) = instantiate(REFUSING_WAT);
+	assert!(mp1_wasmi::send_into(&mut store, &instance, vec![1u8; 10]).is_err());
+}
+
+#[test]
+fn host_reports_signalled_refusals() {
+	let (mut store, instance, out_msgs) = instantiate(SIGNALLED_REFUSAL_WAT);
+	let err = mp1_wasmi::send_into(&mut store, &instance, vec![1u8; 17]).unwrap_err();
+	assert!(err.to_string().contains("refused"));
+	mp1_wasmi::send_into(&mut store, &instance, vec![2u8; 16]).unwrap();
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), [vec![2u8; 16]]);
+}
+
+#[test]
+fn host_reports_msgs_that_are_not_taken() {
+	let (mut store, instance, _) = instantiate(NOT_TAKING_WAT);
+	let err = mp1_wasmi::send_into(&mut store, &instance, vec![1u8; 10]).unwrap_err();
+	assert!(err.to_string().contains("neither took, nor refused"));
+}
+
+#[test]
+fn host_keeps_panic_report_of_trapped_instance() {
+	use wasm_message_passing_3nweb::panic_hook::PanicReport;
+
+	let (mut store, instance, _) = instantiate(PANICKING_WAT);
+	assert!(mp1_wasmi::send_into(&mut store, &instance, vec![1u8; 10]).is_err());
+	assert_eq!(store.data_mut().take_panic_report(), Some(PanicReport {
+		message: "boom".to_string(),
+		file: "src/lib.rs".to_string(),
+		line: 7,
+		column: 3,
+	}));
+	assert_eq!(store.data_mut().take_panic_report(), None);
+}
+
+fn try_send(store: &mut Store<Mp1Ctx>, instance: &Instance, len: u32) -> Result<(), SendError> {
+	let try_send = instance.get_typed_func::<u32, u32>(&mut *store, "try_send").unwrap();
+	SendError::from_status(try_send.call(&mut *store, len).unwrap())
+}
+
+fn send(store: &mut Store<Mp1Ctx>, instance: &Instance, len: u32) {
+	let send = instance.get_typed_func::<u32, ()>(&mut *store, "send").unwrap();
+	send.call(&mut *store, len).unwrap();
+}
+
+#[test]
+fn host_rejects_msgs_above_max_size() {
+	let (mut store, instance, out_msgs) = instantiate(SENDING_WAT);
+	store.data_mut().set_max_out_msg_size(Some(8));
+	assert_eq!(try_send(&mut store, &instance, 9), Err(SendError::TooLarge));
+	assert_eq!(try_send(&mut store, &instance, 8), Ok(()));
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), [b"01234567".to_vec()]);
+}
+
+#[test]
+fn host_rejects_msgs_after_close() {
+	let (mut store, instance, out_msgs) = instantiate(SENDING_WAT);
+	assert_eq!(try_send(&mut store, &instance, 4), Ok(()));
+	store.data_mut().close();
+	assert_eq!(try_send(&mut store, &instance, 4), Err(SendError::Closed));
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), [b"0123".to_vec()]);
+}
+
+#[test]
+fn host_rejects_msgs_that_fail_check() {
+	let (mut store, instance, out_msgs) = instantiate(SENDING_WAT);
+	store.data_mut().set_out_msg_check(|msg| if msg.len() > 2 {
+		Err(SendError::OverQuota)
+	} else {
+		Ok(())
+	});
+	assert_eq!(try_send(&mut store, &instance, 3), Err(SendError::OverQuota));
+	assert_eq!(try_send(&mut store, &instance, 2), Ok(()));
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), [b"01".to_vec()]);
+}
+
+#[test]
+fn host_drops_rejected_msgs_from_legacy_import() {
+	let (mut store, instance, out_msgs) = instantiate(SENDING_WAT);
+	store.data_mut().set_max_out_msg_size(Some(8));
+	store.data_mut().set_out_msg_check(|msg| if msg.len() > 2 {
+		Err(SendError::OverQuota)
+	} else {
+		Ok(())
+	});
+	send(&mut store, &instance, 9);
+	send(&mut store, &instance, 3);
+	send(&mut store, &instance, 2);
+	store.data_mut().close();
+	send(&mut store, &instance, 1);
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), [b"01".to_vec()]);
+}
+
+#[test]
+fn host_gathers_parts_into_one_msg() {
+	let (mut store, instance, out_msgs) = instantiate(SENDING_PARTS_WAT);
+	let send_parts = instance.get_typed_func::<(u32, u32), u32>(
+		&mut store, "send_parts"
+	).unwrap();
+	assert_eq!(send_parts.call(&mut store, (64, 3)).unwrap(), SendError::STATUS_OK);
+	assert_eq!(send_parts.call(&mut store, (64, 0)).unwrap(), SendError::STATUS_OK);
+	assert_eq!(send_parts.call(&mut store, (72, 1)).unwrap(), SendError::STATUS_OK);
+	store.data_mut().set_max_out_msg_size(Some(10));
+	assert_eq!(
+		SendError::from_status(send_parts.call(&mut store, (64, 3)).unwrap()),
+		Err(SendError::TooLarge)
+	);
+	assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), [
+		b"hello world".to_vec(), Vec::new(), Vec::new()
+	]);
+}
+
+#[test]
+fn host_traps_on_malformed_parts() {
+	let (mut store, instance, out_msgs) = instantiate(SENDING_PARTS_WAT);
+	let send_parts = instance.get_typed_func::<(u32, u32), u32>(
+		&mut store, "send_parts"
+	).unwrap();
+	// part's pointer, part's length, and parts themselves are out of bounds
+	for (parts_ptr, num_of_parts) in [
+		(128, 1), (136, 1), (65532, 1), (64, 1 << 29), (64, u32::MAX)
+	] {
+		let err = send_parts.call(&mut store, (parts_ptr, num_of_parts)).unwrap_err();
+		assert!(format!("{err:?}").contains("out of memory bounds"), "{err:?}");
+	}
+	assert_eq!(out_msgs.try_iter().count(), 0);
+}
+
+#[test]
+fn host_reads_versions_descriptor() {
+	let (mut store, instance, _) = instantiate(VERSIONS_WAT);
+	assert_eq!(mp1_wasmi::read_versions(&mut store, &instance).unwrap(), MpVersions {
+		versions: vec![MP1, MP2],
+		max_msg_size: Some(1000),
+		capabilities: Capabilities::GET_BUFFER | Capabilities::SEND_PARTS,
+	});
+}
+
+#[test]
+fn host_rejects_malformed_versions_descriptor() {
+	let (mut store, instance, _) = instantiate(VERSIONS_WAT);
+	let set_ptr = instance.get_typed_func::<u32, ()>(&mut store, "set_ptr").unwrap();
+	// too many versions, cut by the end of memory, and out of memory bounds
+	for ptr in [128, 65528, 65534, 65536, u32::MAX] {
+		set_ptr.call(&mut store, ptr).unwrap();
+		let err = mp1_wasmi::read_versions(&mut store, &instance).unwrap_err();
+		assert!(format!("{err:?}").contains("malformed"), "{err:?}");
+	}
+}
+
+#[test]
+fn host_probes_exports_of_modules_without_versions() {
+	for (wat, versions, capabilities) in [
+		(WRITE_INTO_ECHO_WAT, vec![MP1], Capabilities::NONE),
+		(GET_BUFFER_ECHO_WAT, vec![MP1], Capabilities::GET_BUFFER),
+		(MP2_EXPORTS_WAT, vec![MP2], Capabilities::NONE),
+		(SENDING_WAT, vec![], Capabilities::NONE),
+	] {
+		let (mut store, instance, _) = instantiate(wat);
+		assert_eq!(mp1_wasmi::read_versions(&mut store, &instance).unwrap(), MpVersions {
+			versions, max_msg_size: None, capabilities
+		});
+	}
+}
+
+/// Guest side goes through handshake, selected by `get-buffer` feature.
+#[cfg(feature = "testing")]
+#[test]
+fn guest_accepts_msgs_with_compiled_handshake() {
+	use wasm_message_passing_3nweb::{testing, wasm_mp1};
+
+	wasm_mp1::set_msg_processor(|msg: Vec<u8>| wasm_mp1::send_msg_out(&msg));
+	let msgs = [b"first".to_vec(), Vec::new(), vec![7u8; 1000]];
+	for msg in msgs.iter() {
+		testing::inject_msg(msg.clone());
+	}
+	assert_eq!(testing::take_sent_msgs(), msgs);
+}
+
+/// Guest refuses message above maximum size before allocating memory for it.
+#[cfg(feature = "testing")]
+#[test]
+fn guest_refuses_msgs_above_max_size() {
+	use wasm_message_passing_3nweb::{testing, wasm_mp1};
+
+	wasm_mp1::set_msg_processor(|msg: Vec<u8>| wasm_mp1::send_msg_out(&msg));
+	wasm_mp1::set_max_in_msg_size(Some(10));
+	assert!(testing::inject_msg(vec![1u8; 10]));
+	assert!(!testing::inject_msg(vec![2u8; 11]));
+	assert!(!testing::inject_msg(vec![3u8; 1 << 20]));
+	assert_eq!(testing::take_sent_msgs(), vec![vec![1u8; 10]]);
+}
+
+/// Guest's panic hook reports panic of processor to embedder.
+#[cfg(all(feature = "testing", feature = "panic-hook"))]
+#[test]
+fn guest_reports_panic_of_processor() {
+	use wasm_message_passing_3nweb::{panic_hook, testing, wasm_mp1};
+
+	panic_hook::install();
+	wasm_mp1::set_msg_processor(|_: Vec<u8>| panic!("bad message"));
+	let panicked = std::panic::catch_unwind(|| testing::inject_msg(vec![1u8; 10]));
+	assert!(panicked.is_err());
+	let report = testing::take_panic_report().unwrap();
+	assert_eq!(report.message, "bad message");
+	assert_eq!(report.file, file!());
+}
@@ -0,0 +1,118 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Bursts of messages, passed with version 2, through rings that are much
+//! smaller than bursts, in both directions, with both engines' embeddings.
+
+use wasm_message_passing_3nweb::host::{
+	Mp2Ctx, wasmi as mp_wasmi, wasmtime as mp_wasmtime
+};
+
+/// Echoes every message from inbound ring into outbound one, ringing
+/// embedder's doorbell, when outbound ring has no space for a record, and
+/// after inbound ring is emptied. Both rings have data areas of 256 bytes,
+/// and records are copied byte by byte, wrapping around ends of data areas.
+const RING_ECHO_WAT: &str = r#"(module
+	(import "env" "_3nweb_mp2_ring_doorbell" (func $ring_doorbell))
+	(memory (export "memory") 1)
+	(data (i32.const 16) "\40\00\00\00\08\01\00\00\00\02\00\00\08\01\00\00")
+	(func (export "_3nweb_mp2_rings") (result i32)
+		(i32.const 16))
+	(func $in_byte (param $pos i32) (result i32)
+		(i32.load8_u (i32.add (i32.const 72)
+			(i32.and (local.get $pos) (i32.const 255)))))
+	(func $record_len (param $pos i32) (result i32)
+		(i32.or
+			(i32.or
+				(call $in_byte (local.get $pos))
+				(i32.shl (call $in_byte (i32.add (local.get $pos) (i32.const 1)))
+					(i32.const 8)))
+			(i32.or
+				(i32.shl (call $in_byte (i32.add (local.get $pos) (i32.const 2)))
+					(i32.const 16))
+				(i32.shl (call $in_byte (i32.add (local.get $pos) (i32.const 3)))
+					(i32.const 24)))))
+	(func (export "_3nweb_mp2_doorbell")
+		(local $head i32) (local $tail i32) (local $rec i32) (local $i i32)
+		(block $emptied (loop $next_record
+			(local.set $head (i32.load (i32.const 64)))
+			(br_if $emptied (i32.eq (local.get $head) (i32.load (i32.const 68))))
+			(local.set $rec
+				(i32.add (i32.const 4) (call $record_len (local.get $head))))
+			(if (i32.gt_u (local.get $rec) (i32.sub (i32.const 256)
+					(i32.sub (i32.load (i32.const 516)) (i32.load (i32.const 512)))))
+				(then (call $ring_doorbell)))
+			(local.set $tail (i32.load (i32.const 516)))
+			(local.set $i (i32.const 0))
+			(block $copied (loop $next_byte
+				(br_if $copied (i32.eq (local.get $i) (local.get $rec)))
+				(i32.store8
+					(i32.add (i32.const 520) (i32.and
+						(i32.add (local.get $tail) (local.get $i)) (i32.const 255)))
+					(call $in_byte (i32.add (local.get $head) (local.get $i))))
+				(local.set $i (i32.add (local.get $i) (i32.const 1)))
+				(br $next_byte)))
+			(i32.store (i32.const 516) (i32.add (local.get $tail) (local.get $rec)))
+			(i32.store (i32.const 64) (i32.add (local.get $head) (local.get $rec)))
+			(br $next_record)))
+		(call $ring_doorbell)))"#;
+
+const MSG_COUNT: usize = 300;
+
+/// Burst of messages with lengths from zero up to maximum for rings, which
+/// together are many times larger than rings.
+fn burst() -> Vec<Vec<u8>> {
+	(0..MSG_COUNT).map(|i| {
+		let len = (i * 37) % 253;
+		(0..len).map(|j| (i + j) as u8).collect()
+	})
+	.collect()
+}
+
+#[test]
+fn wasmi_passes_bursts_through_rings() {
+	use wasmi::{Engine, Linker, Module, Store};
+	let engine = Engine::default();
+	let module = Module::new(
+		&engine, wat::parse_str(RING_ECHO_WAT).unwrap()
+	).unwrap();
+	let (ctx, out_msgs) = Mp2Ctx::with_channel();
+	let mut store = Store::new(&engine, ctx);
+	let mut linker = Linker::new(&engine);
+	mp_wasmi::add_mp2_to_linker(&mut linker).unwrap();
+	let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+	for _ in 0..2 {
+		mp_wasmi::send_mp2_msgs_into(&mut store, &instance, burst()).unwrap();
+		assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), burst());
+	}
+}
+
+#[test]
+fn wasmtime_passes_bursts_through_rings() {
+	use wasmtime::{Engine, Linker, Module, Store};
+	let engine = Engine::default();
+	let module = Module::new(
+		&engine, wat::parse_str(RING_ECHO_WAT).unwrap()
+	).unwrap();
+	let (ctx, out_msgs) = Mp2Ctx::with_channel();
+	let mut store = Store::new(&engine, ctx);
+	let mut linker = Linker::new(&engine);
+	mp_wasmtime::add_mp2_to_linker(&mut linker).unwrap();
+	let instance = linker.instantiate(&mut store, &module).unwrap();
+	for _ in 0..2 {
+		mp_wasmtime::send_mp2_msgs_into(&mut store, &instance, burst()).unwrap();
+		assert_eq!(out_msgs.try_iter().collect::<Vec<_>>(), burst());
+	}
+}
@@ -0,0 +1,64 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Comparison of echoing bursts of messages with message passing versions 1
+//! and 2 over mocked embedding. Calls across the boundary are what makes mp1
+//! slow in real embeddings, hence, they are counted.
+
+use wasm_message_passing_3nweb::{testing, wasm_mp1, wasm_mp2};
+
+const MSG_COUNT: usize = 10_000;
+const MSG_LEN: usize = 64;
+
+fn burst() -> impl Iterator<Item = Vec<u8>> {
+	(0..MSG_COUNT).map(|i| {
+		let mut msg = vec![0u8; MSG_LEN];
+		msg[..8].copy_from_slice(&(i as u64).to_le_bytes());
+		msg
+	})
+}
+
+fn assert_echoed_burst(msgs: Vec<Vec<u8>>) {
+	assert_eq!(msgs.len(), MSG_COUNT);
+	assert!(msgs.into_iter().eq(burst()));
+}
+
+fn echo_burst_with_mp1() -> u64 {
+	wasm_mp1::set_msg_processor(|msg: Vec<u8>| wasm_mp1::send_msg_out(&msg));
+	let crossings = testing::boundary_crossings();
+	for msg in burst() {
+		testing::inject_msg(msg);
+	}
+	assert_echoed_burst(testing::take_sent_msgs());
+	testing::boundary_crossings() - crossings
+}
+
+fn echo_burst_with_mp2() -> u64 {
+	wasm_mp2::set_msg_processor(|msg: Vec<u8>| {
+		wasm_mp2::send_msg_out(&msg).unwrap();
+	});
+	let crossings = testing::boundary_crossings();
+	testing::inject_mp2_msgs(burst());
+	assert_echoed_burst(testing::take_sent_msgs());
+	testing::boundary_crossings() - crossings
+}
+
+#[test]
+fn mp2_batches_burst_into_fewer_crossings_than_mp1() {
+	let mp1_crossings = echo_burst_with_mp1();
+	let mp2_crossings = echo_burst_with_mp2();
+	assert!(mp1_crossings >= (3 * MSG_COUNT) as u64);
+	assert!((mp2_crossings * 100) < mp1_crossings);
+}
@@ -0,0 +1,99 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Versions descriptor: its layout, round-trip, rejection of malformed
+//! descriptors, and picking of common version.
+
+use wasm_message_passing_3nweb::mp_versions::{Capabilities, MP1, MP2, MpVersions};
+
+fn fields(values: &[u32]) -> Vec<u8> {
+	values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn versions(versions: &[u32]) -> MpVersions {
+	MpVersions {
+		versions: versions.to_vec(),
+		max_msg_size: None,
+		capabilities: Capabilities::NONE,
+	}
+}
+
+#[test]
+fn descriptor_has_documented_layout() {
+	let descriptor = MpVersions {
+		versions: vec![MP1, MP2],
+		max_msg_size: Some(1000),
+		capabilities: Capabilities::GET_BUFFER | Capabilities::SEND_PARTS,
+	}.to_descriptor();
+	assert_eq!(descriptor, fields(&[2, 1, 2, 1000, 5]));
+	assert_eq!(descriptor.len(), MpVersions::descriptor_len(2));
+	assert_eq!(versions(&[]).to_descriptor(), fields(&[0, 0, 0]));
+}
+
+#[test]
+fn descriptor_round_trips() {
+	let all_versions = (1..=(MpVersions::MAX_VERSIONS as u32)).collect::<Vec<_>>();
+	for original in [
+		versions(&[]),
+		versions(&[MP1]),
+		MpVersions {
+			versions: vec![MP2, MP1],
+			max_msg_size: Some(u32::MAX),
+			capabilities: Capabilities::TRY_SEND | Capabilities::PANIC_REPORT,
+		},
+		MpVersions {
+			versions: all_versions,
+			max_msg_size: Some(1),
+			capabilities: Capabilities::from_bits(u32::MAX),
+		},
+	] {
+		let descriptor = original.to_descriptor();
+		assert_eq!(MpVersions::from_descriptor(&descriptor), Some(original));
+	}
+}
+
+#[test]
+fn malformed_descriptors_are_rejected() {
+	let too_many = MpVersions::MAX_VERSIONS as u32 + 1;
+	let mut above_max = vec![too_many];
+	above_max.extend(1..=too_many);
+	above_max.extend([0, 0]);
+	assert_eq!(MpVersions::from_descriptor(&fields(&above_max)), None);
+	assert_eq!(MpVersions::num_of_versions(&too_many.to_le_bytes()), None);
+	assert_eq!(MpVersions::num_of_versions(&u32::MAX.to_le_bytes()), None);
+
+	let descriptor = fields(&[2, 1, 2, 0, 0]);
+	for len in 0..descriptor.len() {
+		assert_eq!(MpVersions::from_descriptor(&descriptor[..len]), None, "{len}");
+	}
+	let mut longer = descriptor.clone();
+	longer.extend_from_slice(&[0; 4]);
+	assert_eq!(MpVersions::from_descriptor(&longer), None);
+	assert!(MpVersions::from_descriptor(&descriptor).is_some());
+}
+
+#[test]
+fn highest_common_version_is_picked() {
+	assert_eq!(versions(&[MP1, MP2]).pick(&[MP1, MP2]), Some(MP2));
+	assert_eq!(versions(&[MP2, MP1]).pick(&[MP1, MP2]), Some(MP2));
+	assert_eq!(versions(&[MP1, MP2]).pick(&[MP1]), Some(MP1));
+	assert_eq!(versions(&[MP1]).pick(&[MP2, MP1]), Some(MP1));
+	assert_eq!(versions(&[MP1, 5, 3]).pick(&[3, 5, 7]), Some(5));
+	assert_eq!(versions(&[MP2]).pick(&[MP1]), None);
+	assert_eq!(versions(&[]).pick(&[MP1, MP2]), None);
+	assert_eq!(versions(&[MP1]).pick(&[]), None);
+	assert!(versions(&[MP1, 5]).supports(5));
+	assert!(!versions(&[MP1, 5]).supports(MP2));
+}
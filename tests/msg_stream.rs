@@ -0,0 +1,77 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Stream of incoming messages on mocked embedding: both overflow policies,
+//! end of stream, and removal of its processor.
+
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use wasm_message_passing_3nweb::msg_stream::{self, IncomingMsgs, Overflow};
+use wasm_message_passing_3nweb::{testing, wasm_mp1};
+
+fn poll_next(msgs: &mut IncomingMsgs) -> Poll<Option<Vec<u8>>> {
+	Pin::new(msgs).poll_next(&mut Context::from_waker(Waker::noop()))
+}
+
+fn inject_numbered(range: std::ops::Range<u8>) {
+	for i in range {
+		testing::inject_msg(vec![i]);
+	}
+}
+
+#[test]
+fn full_queue_drops_newest_msgs() {
+	let mut msgs = msg_stream::incoming_msgs(2, Overflow::DropNewest);
+	inject_numbered(0..5);
+	assert_eq!(msgs.dropped_count(), 3);
+	assert_eq!(poll_next(&mut msgs), Poll::Ready(Some(vec![0])));
+	assert_eq!(poll_next(&mut msgs), Poll::Ready(Some(vec![1])));
+	assert_eq!(poll_next(&mut msgs), Poll::Pending);
+}
+
+#[test]
+fn full_queue_drops_oldest_msgs() {
+	let mut msgs = msg_stream::incoming_msgs(2, Overflow::DropOldest);
+	inject_numbered(0..5);
+	assert_eq!(msgs.dropped_count(), 3);
+	assert_eq!(msgs.queued_count(), 2);
+	assert_eq!(poll_next(&mut msgs), Poll::Ready(Some(vec![3])));
+	assert_eq!(poll_next(&mut msgs), Poll::Ready(Some(vec![4])));
+	assert_eq!(poll_next(&mut msgs), Poll::Pending);
+}
+
+#[test]
+fn stream_ends_when_processor_is_replaced() {
+	let mut msgs = msg_stream::incoming_msgs(4, Overflow::DropNewest);
+	inject_numbered(0..1);
+	wasm_mp1::set_msg_processor(|_: Vec<u8>| ());
+	inject_numbered(1..2);
+	assert_eq!(poll_next(&mut msgs), Poll::Ready(Some(vec![0])));
+	assert_eq!(poll_next(&mut msgs), Poll::Ready(None));
+}
+
+#[test]
+fn dropped_stream_takes_its_processor() {
+	let msgs = msg_stream::incoming_msgs(4, Overflow::DropNewest);
+	drop(msgs);
+	assert!(wasm_mp1::take_msg_processor().is_none());
+
+	// stream doesn't take processor, which has replaced its own
+	let msgs = msg_stream::incoming_msgs(4, Overflow::DropNewest);
+	wasm_mp1::set_msg_processor(|_: Vec<u8>| ());
+	drop(msgs);
+	assert!(wasm_mp1::take_msg_processor().is_some());
+}
@@ -0,0 +1,82 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Channels of `mux` on mocked embedding: routing of messages to processors
+//! of channels, and fallback for unknown channels and short messages.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_message_passing_3nweb::mux::{self, ChannelInUse, Unrouted};
+use wasm_message_passing_3nweb::testing;
+
+type Received<T> = Rc<RefCell<Vec<T>>>;
+
+fn collector<T: 'static>() -> (Received<T>, impl FnMut(T) + 'static) {
+	let received = Rc::new(RefCell::new(Vec::new()));
+	let sink = received.clone();
+	(received, move |item| sink.borrow_mut().push(item))
+}
+
+#[test]
+fn msgs_are_routed_to_their_channels() {
+	mux::install();
+	let (first, first_processor) = collector();
+	let (second, second_processor) = collector();
+	let _first = mux::open_channel(1, first_processor).unwrap();
+	let _second = mux::open_channel(2, second_processor).unwrap();
+	testing::inject_msg(mux::encode(2, b"b"));
+	testing::inject_msg(mux::encode(1, b"a"));
+	testing::inject_msg(mux::encode(2, b""));
+	assert_eq!(first.take(), [b"a".to_vec()]);
+	assert_eq!(second.take(), [b"b".to_vec(), Vec::new()]);
+}
+
+#[test]
+fn channel_sends_prefixed_msgs() {
+	let channel = mux::open_channel(7, |_| ()).unwrap();
+	assert_eq!(mux::open_channel(7, |_| ()).err(), Some(ChannelInUse(7)));
+	channel.send(b"hello");
+	assert_eq!(testing::take_sent_msgs(), [mux::encode(7, b"hello")]);
+	assert_eq!(mux::decode(mux::encode(7, b"hello")), Some((7, b"hello".to_vec())));
+}
+
+#[test]
+fn msgs_of_unknown_channels_fall_back() {
+	mux::install();
+	let (unrouted, fallback) = collector();
+	mux::set_fallback(fallback);
+	let channel = mux::open_channel(1, |_| ()).unwrap();
+	testing::inject_msg(mux::encode(5, b"lost"));
+	drop(channel);
+	testing::inject_msg(mux::encode(1, b"late"));
+	assert_eq!(unrouted.take(), [
+		Unrouted::UnknownChannel { channel_id: 5, body: b"lost".to_vec() },
+		Unrouted::UnknownChannel { channel_id: 1, body: b"late".to_vec() },
+	]);
+}
+
+#[test]
+fn short_msgs_fall_back_whole() {
+	mux::install();
+	let (unrouted, fallback) = collector();
+	mux::set_fallback(fallback);
+	testing::inject_msg(vec![1, 2, 3]);
+	testing::inject_msg(Vec::new());
+	assert_eq!(unrouted.take(), [
+		Unrouted::Malformed(vec![1, 2, 3]),
+		Unrouted::Malformed(Vec::new()),
+	]);
+	assert_eq!(mux::decode(vec![1, 2, 3]), None);
+}
@@ -0,0 +1,84 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Echo guest, that uses `wasm_mp1` under `no_std`, with its own global
+//! allocator and panic handler, as guests do. It is built with
+//!
+//! ```sh
+//! cargo build --release --target wasm32-unknown-unknown
+//! ```
+//!
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::alloc::{GlobalAlloc, Layout};
+use core::arch::wasm32;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use wasm_message_passing_3nweb::wasm_mp1;
+
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Allocator, that takes memory from pages, added to the end of memory, and
+/// never frees it.
+///
+struct BumpAlloc {
+	next: AtomicUsize,
+	end: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for BumpAlloc {
+
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let mut start = (self.next.load(Ordering::Relaxed) + layout.align() - 1)
+		& !(layout.align() - 1);
+		let mut end = self.end.load(Ordering::Relaxed);
+		if (start == 0) || (start + layout.size() > end) {
+			let pages = layout.size().div_ceil(PAGE_SIZE) + 1;
+			let first_page = wasm32::memory_grow(0, pages);
+			if first_page == usize::MAX {
+				return core::ptr::null_mut();
+			}
+			start = first_page * PAGE_SIZE;
+			end = start + pages * PAGE_SIZE;
+			self.end.store(end, Ordering::Relaxed);
+		}
+		self.next.store(start + layout.size(), Ordering::Relaxed);
+		start as *mut u8
+	}
+
+	unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+
+}
+
+#[global_allocator]
+static ALLOC: BumpAlloc = BumpAlloc {
+	next: AtomicUsize::new(0),
+	end: AtomicUsize::new(0),
+};
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+	wasm32::unreachable()
+}
+
+/// Starts guest, which sends every incoming message back.
+///
+#[no_mangle]
+pub extern "C" fn _start_echo() {
+	wasm_mp1::set_msg_processor(|msg: Vec<u8>| wasm_mp1::send_msg_out(&msg));
+}
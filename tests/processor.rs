@@ -0,0 +1,131 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Message processors of `wasm_mp1` on mocked embedding: taking processor
+//! out, reentrancy of processing, and slice processor over receive buffer.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_message_passing_3nweb::{testing, wasm_mp1};
+
+/// Log of events, shared by processors and test.
+#[derive(Clone, Default)]
+struct Events(Rc<RefCell<Vec<String>>>);
+
+impl Events {
+	fn push(&self, event: impl Into<String>) {
+		self.0.borrow_mut().push(event.into());
+	}
+	fn take(&self) -> Vec<String> {
+		self.0.take()
+	}
+}
+
+fn as_text(msg: &[u8]) -> &str {
+	std::str::from_utf8(msg).unwrap()
+}
+
+#[test]
+fn taken_processor_is_given_back() {
+	let events = Events::default();
+	let log = events.clone();
+	wasm_mp1::set_msg_processor(move |msg: Vec<u8>| log.push(as_text(&msg)));
+	testing::inject_msg(b"first".to_vec());
+
+	let mut processor = wasm_mp1::take_msg_processor().unwrap();
+	assert!(wasm_mp1::take_msg_processor().is_none());
+	// message without processor is dropped
+	testing::inject_msg(b"dropped".to_vec());
+	processor(b"by hand".to_vec());
+
+	let log = events.clone();
+	assert!(wasm_mp1::replace_msg_processor(move |msg: Vec<u8>| {
+		log.push(format!("new {}", as_text(&msg)));
+	}).is_none());
+	testing::inject_msg(b"second".to_vec());
+	assert_eq!(events.take(), ["first", "by hand", "new second"]);
+}
+
+#[test]
+fn msgs_within_processing_are_queued() {
+	let events = Events::default();
+	let log = events.clone();
+	wasm_mp1::set_msg_processor(move |msg: Vec<u8>| {
+		log.push(format!("start {}", as_text(&msg)));
+		if msg == b"outer" {
+			wasm_mp1::send_msg_out(b"reply");
+			// embedder reenters from within its send callback
+			testing::inject_msg(b"inner".to_vec());
+		}
+		log.push(format!("end {}", as_text(&msg)));
+	});
+	testing::inject_msg(b"outer".to_vec());
+	assert_eq!(events.take(), ["start outer", "end outer", "start inner", "end inner"]);
+	assert_eq!(testing::take_sent_msgs(), [b"reply".to_vec()]);
+}
+
+#[test]
+fn processor_replaced_within_processing() {
+	let events = Events::default();
+	let log = events.clone();
+	wasm_mp1::set_msg_processor(move |msg: Vec<u8>| {
+		log.push(format!("old {}", as_text(&msg)));
+		let new_log = log.clone();
+		wasm_mp1::set_msg_processor(move |msg: Vec<u8>| {
+			new_log.push(format!("new {}", as_text(&msg)));
+		});
+		testing::inject_msg(b"queued".to_vec());
+		log.push("old is still running");
+	});
+	testing::inject_msg(b"first".to_vec());
+	testing::inject_msg(b"second".to_vec());
+	assert_eq!(events.take(), [
+		"old first", "old is still running", "new queued", "new second"
+	]);
+}
+
+#[test]
+fn processor_taken_within_processing() {
+	let events = Events::default();
+	let log = events.clone();
+	wasm_mp1::set_msg_processor(move |msg: Vec<u8>| {
+		log.push(as_text(&msg));
+		// running processor can't be given away
+		log.push(format!("taken: {}", wasm_mp1::take_msg_processor().is_some()));
+	});
+	testing::inject_msg(b"first".to_vec());
+	testing::inject_msg(b"dropped".to_vec());
+	assert_eq!(events.take(), ["first", "taken: false"]);
+	assert!(wasm_mp1::take_msg_processor().is_none());
+}
+
+#[test]
+fn slice_processor_reads_recycled_buffer() {
+	let addrs = Rc::new(RefCell::new(Vec::new()));
+	let events = Events::default();
+	let (log, seen_addrs) = (events.clone(), addrs.clone());
+	wasm_mp1::set_msg_slice_processor(move |msg: &[u8]| {
+		seen_addrs.borrow_mut().push(msg.as_ptr() as usize);
+		log.push(as_text(msg));
+	});
+	for msg in ["longest message", "short", "", "shorter"] {
+		testing::inject_msg(msg.as_bytes().to_vec());
+	}
+	assert_eq!(events.take(), ["longest message", "short", "", "shorter"]);
+	// messages, that fit into receive buffer, are written into the same memory
+	let addrs = addrs.take();
+	assert_eq!(addrs[1], addrs[0]);
+	assert_eq!(addrs[3], addrs[0]);
+}
@@ -0,0 +1,179 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use wasm_message_passing_3nweb::wasm_mp2::ring::{
+	HEADER_LEN, PushError, RECORD_HEADER_LEN, Ring
+};
+
+const CAPACITY: usize = 64;
+
+fn area() -> Vec<u8> {
+	vec![0u8; Ring::area_len(CAPACITY)]
+}
+
+fn set_positions(area: &mut [u8], head: u32, tail: u32) {
+	area[0..4].copy_from_slice(&head.to_le_bytes());
+	area[4..8].copy_from_slice(&tail.to_le_bytes());
+}
+
+fn positions(area: &[u8]) -> (u32, u32) {
+	let mut head = [0u8; 4];
+	let mut tail = [0u8; 4];
+	head.copy_from_slice(&area[0..4]);
+	tail.copy_from_slice(&area[4..8]);
+	(u32::from_le_bytes(head), u32::from_le_bytes(tail))
+}
+
+fn msg(seed: u8, len: usize) -> Vec<u8> {
+	(0..len).map(|i| seed.wrapping_add(i as u8)).collect()
+}
+
+#[test]
+fn data_area_must_be_power_of_two() {
+	for capacity in [0, 3, 12, 63, 65, 100] {
+		let mut area = vec![0u8; HEADER_LEN + capacity];
+		assert!(Ring::new(&mut area).is_none(), "capacity {capacity}");
+	}
+	let mut short_area = vec![0u8; HEADER_LEN - 1];
+	assert!(Ring::new(&mut short_area).is_none());
+	for capacity in [8, 16, 64, 1024] {
+		let mut area = vec![0u8; Ring::area_len(capacity)];
+		let ring = Ring::new(&mut area).unwrap();
+		assert_eq!(ring.capacity(), capacity);
+		assert_eq!(ring.max_msg_len(), capacity - RECORD_HEADER_LEN);
+	}
+}
+
+#[test]
+fn messages_come_out_in_order() {
+	let mut area = area();
+	let mut ring = Ring::new(&mut area).unwrap();
+	assert!(ring.is_empty());
+	assert_eq!(ring.pop(), None);
+	ring.push(b"first").unwrap();
+	ring.push(b"").unwrap();
+	ring.push(b"third").unwrap();
+	assert_eq!(ring.used(), 3 * RECORD_HEADER_LEN + 10);
+	assert_eq!(ring.pop().unwrap(), b"first");
+	assert_eq!(ring.pop().unwrap(), b"");
+	assert_eq!(ring.pop().unwrap(), b"third");
+	assert_eq!(ring.pop(), None);
+	assert!(ring.is_empty());
+}
+
+#[test]
+fn too_large_msg_is_rejected_even_in_empty_ring() {
+	let mut area = area();
+	let mut ring = Ring::new(&mut area).unwrap();
+	let max_len = ring.max_msg_len();
+	assert_eq!(ring.push(&msg(0, max_len + 1)), Err(PushError::TooLarge));
+	assert!(ring.is_empty());
+	ring.push(&msg(0, max_len)).unwrap();
+	assert_eq!(ring.used(), CAPACITY);
+	assert_eq!(ring.pop().unwrap(), msg(0, max_len));
+}
+
+#[test]
+fn full_ring_takes_msgs_after_pops() {
+	let mut area = area();
+	let mut ring = Ring::new(&mut area).unwrap();
+	// records of 16 bytes fill data area exactly
+	for i in 0..4 {
+		ring.push(&msg(i, 12)).unwrap();
+	}
+	assert_eq!(ring.push(b""), Err(PushError::Full));
+	assert_eq!(ring.pop().unwrap(), msg(0, 12));
+	assert_eq!(ring.push(&msg(4, 13)), Err(PushError::Full));
+	ring.push(&msg(4, 12)).unwrap();
+	assert_eq!(ring.push(b""), Err(PushError::Full));
+	for i in 1..5 {
+		assert_eq!(ring.pop().unwrap(), msg(i, 12));
+	}
+	assert!(ring.is_empty());
+}
+
+#[test]
+fn records_wrap_around_end_of_data_area() {
+	let mut area = area();
+	let mut ring = Ring::new(&mut area).unwrap();
+	// odd lengths put record headers and bodies across the end of data area
+	for round in 0..50u8 {
+		let len = 1 + ((round as usize * 7) % 30);
+		ring.push(&msg(round, len)).unwrap();
+		ring.push(&msg(round.wrapping_add(100), 3)).unwrap();
+		assert_eq!(ring.pop().unwrap(), msg(round, len));
+		assert_eq!(ring.pop().unwrap(), msg(round.wrapping_add(100), 3));
+	}
+	assert!(ring.is_empty());
+}
+
+#[test]
+fn positions_wrap_at_u32_bounds() {
+	let mut area = area();
+	let start = u32::MAX - 5;
+	set_positions(&mut area, start, start);
+	let mut ring = Ring::new(&mut area).unwrap();
+	assert!(ring.is_empty());
+	ring.push(&msg(1, 10)).unwrap();
+	ring.push(&msg(2, 20)).unwrap();
+	assert_eq!(ring.used(), 2 * RECORD_HEADER_LEN + 30);
+	assert_eq!(ring.pop().unwrap(), msg(1, 10));
+	ring.push(&msg(3, 20)).unwrap();
+	assert_eq!(ring.push(&msg(4, 20)), Err(PushError::Full));
+	assert_eq!(ring.pop().unwrap(), msg(2, 20));
+	assert_eq!(ring.pop().unwrap(), msg(3, 20));
+	assert!(ring.is_empty());
+	let (head, tail) = positions(&area);
+	assert_eq!(head, tail);
+	assert_eq!(head, start.wrapping_add((3 * RECORD_HEADER_LEN + 50) as u32));
+	assert!(head < start);
+}
+
+#[test]
+fn ring_resets_when_positions_are_corrupt() {
+	let mut area = area();
+	set_positions(&mut area, 10, 10 + (CAPACITY as u32) + 1);
+	let mut ring = Ring::new(&mut area).unwrap();
+	assert_eq!(ring.pop(), None);
+	assert!(ring.is_empty());
+	assert_eq!(positions(&area), (0, 0));
+
+	// tail behind head looks like more used bytes than capacity
+	set_positions(&mut area, 20, 10);
+	let mut ring = Ring::new(&mut area).unwrap();
+	assert_eq!(ring.pop(), None);
+	assert_eq!(positions(&area), (0, 0));
+
+	// used bytes can't hold even a record header
+	set_positions(&mut area, 0, (RECORD_HEADER_LEN as u32) - 1);
+	let mut ring = Ring::new(&mut area).unwrap();
+	assert_eq!(ring.pop(), None);
+	assert_eq!(positions(&area), (0, 0));
+
+	let mut ring = Ring::new(&mut area).unwrap();
+	ring.push(b"after reset").unwrap();
+	assert_eq!(ring.pop().unwrap(), b"after reset");
+}
+
+#[test]
+fn ring_resets_when_record_is_longer_than_used_bytes() {
+	let mut area = area();
+	Ring::new(&mut area).unwrap().push(b"msg").unwrap();
+	area[HEADER_LEN..(HEADER_LEN + 4)].copy_from_slice(&4u32.to_le_bytes());
+	let mut ring = Ring::new(&mut area).unwrap();
+	assert_eq!(ring.pop(), None);
+	assert!(ring.is_empty());
+	assert_eq!(positions(&area), (0, 0));
+}
@@ -0,0 +1,130 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Calls of `rpc` in both directions on mocked embedding.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use wasm_message_passing_3nweb::rpc::{self, Frame, FrameKind, RpcError};
+use wasm_message_passing_3nweb::testing;
+
+/// Waker that counts its wakes.
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+	fn wake(self: Arc<Self>) {
+		self.0.fetch_add(1, Ordering::SeqCst);
+	}
+}
+
+fn poll<F: Future + Unpin>(fut: &mut F, waker: &Waker) -> Poll<F::Output> {
+	Pin::new(fut).poll(&mut Context::from_waker(waker))
+}
+
+/// Takes the only request frame, sent by guest.
+fn take_request() -> Frame {
+	let mut sent = testing::take_sent_msgs();
+	assert_eq!(sent.len(), 1);
+	let frame = Frame::decode(sent.remove(0)).unwrap();
+	assert_eq!(frame.kind, FrameKind::Request);
+	frame
+}
+
+fn inject_frame(kind: FrameKind, call_id: u32, body: &[u8]) {
+	testing::inject_msg(Frame { kind, call_id, body: body.to_vec() }.encode());
+}
+
+#[test]
+fn call_resolves_after_reply_comes() {
+	rpc::install();
+	let counter = Arc::new(CountingWaker::default());
+	let waker = Waker::from(counter.clone());
+	let mut call = rpc::call(b"ping");
+	let request = take_request();
+	assert_eq!(request.body, b"ping");
+	assert_eq!(poll(&mut call, &waker), Poll::Pending);
+
+	inject_frame(FrameKind::Reply, request.call_id, b"pong");
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+	assert_eq!(poll(&mut call, &waker), Poll::Ready(Ok(b"pong".to_vec())));
+}
+
+#[test]
+fn error_frame_fails_call() {
+	rpc::install();
+	let mut call = rpc::call(b"ping");
+	let request = take_request();
+	inject_frame(FrameKind::Error, request.call_id, b"no such thing");
+	assert_eq!(
+		poll(&mut call, Waker::noop()),
+		Poll::Ready(Err(RpcError::Remote(b"no such thing".to_vec())))
+	);
+}
+
+#[test]
+fn replies_to_unknown_calls_are_ignored() {
+	rpc::install();
+	let mut call = rpc::call(b"ping");
+	let request = take_request();
+	let unknown_id = request.call_id.wrapping_add(100);
+	inject_frame(FrameKind::Reply, unknown_id, b"stray");
+	inject_frame(FrameKind::Error, unknown_id, b"stray");
+	// messages, that aren't frames, are ignored as well
+	testing::inject_msg(vec![9, 1]);
+	assert_eq!(poll(&mut call, Waker::noop()), Poll::Pending);
+	assert!(testing::take_sent_msgs().is_empty());
+
+	inject_frame(FrameKind::Reply, request.call_id, b"pong");
+	assert_eq!(poll(&mut call, Waker::noop()), Poll::Ready(Ok(b"pong".to_vec())));
+}
+
+#[test]
+fn requests_from_outside_are_answered() {
+	rpc::install();
+	inject_frame(FrameKind::Request, 5, b"before handler");
+	rpc::set_request_handler(|request| match request.as_slice() {
+		b"fail" => Err(b"failed".to_vec()),
+		_ => Ok([b"re: ".as_slice(), &request].concat()),
+	});
+	inject_frame(FrameKind::Request, 6, b"hi");
+	inject_frame(FrameKind::Request, 7, b"fail");
+	let answers = testing::take_sent_msgs().into_iter()
+	.map(|msg| Frame::decode(msg).unwrap())
+	.collect::<Vec<_>>();
+	assert_eq!(answers, [
+		Frame { kind: FrameKind::Error, call_id: 5, body: Vec::new() },
+		Frame { kind: FrameKind::Reply, call_id: 6, body: b"re: hi".to_vec() },
+		Frame { kind: FrameKind::Error, call_id: 7, body: b"failed".to_vec() },
+	]);
+}
+
+#[test]
+fn uninstall_closes_pending_calls() {
+	rpc::install();
+	let counter = Arc::new(CountingWaker::default());
+	let waker = Waker::from(counter.clone());
+	let mut call = rpc::call(b"ping");
+	let request = take_request();
+	assert_eq!(poll(&mut call, &waker), Poll::Pending);
+	rpc::uninstall();
+	assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+	assert_eq!(poll(&mut call, &waker), Poll::Ready(Err(RpcError::Closed)));
+	// late reply reaches no processor
+	inject_frame(FrameKind::Reply, request.call_id, b"pong");
+}
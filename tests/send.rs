@@ -0,0 +1,111 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Sending messages out with `wasm_mp1` on mocked embedding: status codes of
+//! embedder, and rejections, reported with `try-send` feature, or silently
+//! dropped by legacy import without it. Messages, sent in parts, arrive as one
+//! message, when parts are coalesced in send buffer, and when they are
+//! gathered by embedder with `send-parts` feature.
+
+use wasm_message_passing_3nweb::{testing, wasm_mp1};
+use wasm_message_passing_3nweb::wasm_mp1::SendError;
+
+const REJECTIONS: [SendError; 4] = [
+	SendError::TooLarge, SendError::Closed, SendError::OverQuota,
+	SendError::Other(42)
+];
+
+#[test]
+fn status_codes_map_to_errors() {
+	assert_eq!(SendError::from_status(SendError::STATUS_OK), Ok(()));
+	assert_eq!(SendError::from_status(1), Err(SendError::TooLarge));
+	assert_eq!(SendError::from_status(2), Err(SendError::Closed));
+	assert_eq!(SendError::from_status(3), Err(SendError::OverQuota));
+	assert_eq!(SendError::from_status(4), Err(SendError::Other(4)));
+	assert_eq!(SendError::from_status(u32::MAX), Err(SendError::Other(u32::MAX)));
+	for error in REJECTIONS {
+		assert_eq!(SendError::from_status(error.status()), Err(error));
+	}
+}
+
+#[cfg(feature = "try-send")]
+#[test]
+fn rejections_are_reported() {
+	for error in REJECTIONS {
+		testing::reject_sent_msgs(Some(error));
+		assert_eq!(wasm_mp1::try_send_msg_out(b"rejected"), Err(error));
+		// sending, that ignores status, doesn't fail on rejection
+		wasm_mp1::send_msg_out(b"rejected");
+		wasm_mp1::send(b"rejected");
+	}
+	testing::reject_sent_msgs(None);
+	assert_eq!(wasm_mp1::try_send_msg_out(b"taken"), Ok(()));
+	assert_eq!(testing::take_sent_msgs(), [b"taken".to_vec()]);
+}
+
+#[cfg(not(feature = "try-send"))]
+#[test]
+fn legacy_import_drops_rejected_msgs() {
+	for error in REJECTIONS {
+		testing::reject_sent_msgs(Some(error));
+		assert_eq!(wasm_mp1::try_send_msg_out(b"rejected"), Ok(()));
+		wasm_mp1::send_msg_out(b"rejected");
+	}
+	testing::reject_sent_msgs(None);
+	assert_eq!(wasm_mp1::try_send_msg_out(b"taken"), Ok(()));
+	assert_eq!(testing::take_sent_msgs(), [b"taken".to_vec()]);
+}
+
+#[test]
+fn any_byte_source_is_sent() {
+	wasm_mp1::send(b"array");
+	wasm_mp1::send(&b"slice"[..]);
+	wasm_mp1::send(vec![b'v', b'e', b'c']);
+	wasm_mp1::send(String::from("string"));
+	wasm_mp1::send("");
+	assert_eq!(testing::take_sent_msgs(), [
+		b"array".to_vec(), b"slice".to_vec(), b"vec".to_vec(),
+		b"string".to_vec(), Vec::new()
+	]);
+}
+
+#[test]
+fn parts_arrive_as_one_msg() {
+	wasm_mp1::send_parts(&[b"head", b"", b"body"]);
+	wasm_mp1::send_parts(&[]);
+	wasm_mp1::send_parts(&[b"", b""]);
+	assert_eq!(wasm_mp1::try_send_parts(&[&[7u8; 1000], b"tail"]), Ok(()));
+	// shorter message after longer one has nothing left from it
+	assert_eq!(wasm_mp1::try_send_parts(&[b"short"]), Ok(()));
+	let mut long_msg = vec![7u8; 1000];
+	long_msg.extend_from_slice(b"tail");
+	assert_eq!(testing::take_sent_msgs(), [
+		b"headbody".to_vec(), Vec::new(), Vec::new(), long_msg, b"short".to_vec()
+	]);
+}
+
+// parts are rejected only with imports, that return status
+#[cfg(any(feature = "try-send", feature = "send-parts"))]
+#[test]
+fn rejected_parts_are_reported() {
+	for error in REJECTIONS {
+		testing::reject_sent_msgs(Some(error));
+		assert_eq!(wasm_mp1::try_send_parts(&[b"rejected", b""]), Err(error));
+		wasm_mp1::send_parts(&[b"rejected"]);
+	}
+	testing::reject_sent_msgs(None);
+	assert_eq!(wasm_mp1::try_send_parts(&[b"ta", b"ken"]), Ok(()));
+	assert_eq!(testing::take_sent_msgs(), [b"taken".to_vec()]);
+}
@@ -0,0 +1,191 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Service, declared with `mp1_service`, is called with generated client, and
+//! serves requests over rpc on mocked embedding.
+
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+use wasm_message_passing_3nweb::rpc::{Frame, FrameKind};
+use wasm_message_passing_3nweb::service::{
+	self, mp1_service, Codec, Dispatch, ServiceError, Transport
+};
+use wasm_message_passing_3nweb::testing;
+use wasm_message_passing_3nweb::typed::JsonCodec;
+
+#[mp1_service]
+pub trait Calculator {
+	/// Adds given numbers.
+	fn add(&mut self, a: i32, b: i32) -> i32;
+	fn greet(&self, name: String) -> String;
+	fn reset(&mut self);
+}
+
+#[derive(Default)]
+struct Calc {
+	resets: u32,
+}
+
+impl Calculator for Calc {
+	fn add(&mut self, a: i32, b: i32) -> i32 {
+		a + b
+	}
+	fn greet(&self, name: String) -> String {
+		format!("Hello, {name}!")
+	}
+	fn reset(&mut self) {
+		self.resets += 1;
+	}
+}
+
+/// Transport that gives requests straight to a dispatcher.
+struct Direct<D>(D);
+
+impl<D: Dispatch> Transport for Direct<D> {
+	fn call(
+		&mut self, request: Vec<u8>
+	) -> impl Future<Output = Result<Vec<u8>, ServiceError>> {
+		std::future::ready(self.0.dispatch(&request))
+	}
+}
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+	let mut fut = pin!(fut);
+	let mut cx = Context::from_waker(Waker::noop());
+	match fut.as_mut().poll(&mut cx) {
+		Poll::Ready(output) => output,
+		Poll::Pending => panic!("future should be ready"),
+	}
+}
+
+#[test]
+fn client_calls_service_methods() {
+	let dispatcher = CalculatorDispatcher::new(Calc::default(), JsonCodec);
+	let mut client = CalculatorClient::new(Direct(dispatcher), JsonCodec);
+	assert_eq!(block_on(client.add(2, 3)), Ok(5));
+	assert_eq!(
+		block_on(client.greet("Bob".to_string())),
+		Ok("Hello, Bob!".to_string())
+	);
+	assert_eq!(block_on(client.reset()), Ok(()));
+	assert_eq!(client.transport().0.service().resets, 1);
+}
+
+#[test]
+fn dispatcher_reports_bad_requests() {
+	let mut dispatcher = CalculatorDispatcher::new(Calc::default(), JsonCodec);
+	assert_eq!(
+		dispatcher.dispatch(&service::encode_request("divide", b"[1,2]")),
+		Err(ServiceError::UnknownMethod("divide".to_string()))
+	);
+	assert!(matches!(
+		dispatcher.dispatch(&service::encode_request("add", b"[\"x\"]")),
+		Err(ServiceError::BadRequest(_))
+	));
+	assert!(matches!(
+		dispatcher.dispatch(&[5]), Err(ServiceError::BadRequest(_))
+	));
+}
+
+#[test]
+fn guest_serves_requests_over_rpc() {
+	service::serve(CalculatorDispatcher::new(Calc::default(), JsonCodec));
+	let request = |call_id, method, args: &[u8]| Frame {
+		kind: FrameKind::Request,
+		call_id,
+		body: service::encode_request(method, args),
+	}.encode();
+	testing::inject_msg(request(7, "add", b"[40,2]"));
+	testing::inject_msg(request(8, "divide", b"[]"));
+	let replies = testing::take_sent_msgs().into_iter()
+	.map(|msg| Frame::decode(msg).unwrap())
+	.collect::<Vec<_>>();
+	assert_eq!(replies[0].kind, FrameKind::Reply);
+	assert_eq!(replies[0].call_id, 7);
+	assert_eq!(JsonCodec.decode::<i32>(&replies[0].body).unwrap(), 42);
+	assert_eq!(replies[1].kind, FrameKind::Error);
+	assert_eq!(
+		ServiceError::from_error_body(replies[1].body.clone()),
+		ServiceError::UnknownMethod("divide".to_string())
+	);
+}
+
+/// Service in WASM, called by embedder with generated client.
+#[cfg(feature = "wasmi")]
+mod host_client {
+
+	use std::sync::mpsc;
+	use super::block_on;
+	use wasm_message_passing_3nweb::host::calls::HostCalls;
+	use wasm_message_passing_3nweb::host::wasmi::{
+		self as mp1_wasmi, Mp1Instance, ServiceTransport
+	};
+	use wasm_message_passing_3nweb::service::mp1_service;
+	use wasm_message_passing_3nweb::typed::JsonCodec;
+	use wasmi::{Engine, Linker, Module, Store};
+
+	// implemented only by WAT guest below
+	#[allow(dead_code)]
+	#[mp1_service]
+	pub trait Echo {
+		/// Returns arguments of the call, as guest below sends back encoded
+		/// arguments of every request.
+		fn echo(&self, word: String) -> Vec<String>;
+	}
+
+	/// Replies to every request with its arguments, turning request frame
+	/// into reply frame in place.
+	const ECHO_SERVICE_WAT: &str = r#"(module
+		(import "env" "_3nweb_mp1_send_out_msg" (func $send (param i32 i32)))
+		(import "env" "_3nweb_mp1_write_msg_into" (func $write (param i32)))
+		(memory (export "memory") 1)
+		(func (export "_3nweb_mp1_accept_msg") (param $len i32)
+			(local $name_len i32) (local $start i32)
+			(call $write (i32.const 1024))
+			(local.set $name_len (i32.load16_u (i32.const 1029)))
+			(local.set $start (i32.add (i32.const 1026) (local.get $name_len)))
+			(i32.store (i32.add (local.get $start) (i32.const 1)) (i32.load (i32.const 1025)))
+			(i32.store8 (local.get $start) (i32.const 2))
+			(call $send
+				(local.get $start)
+				(i32.sub (local.get $len) (i32.add (i32.const 2) (local.get $name_len))))))"#;
+
+	#[test]
+	fn host_calls_service_in_wasm() {
+		let engine = Engine::default();
+		let module = Module::new(&engine, wat::parse_str(ECHO_SERVICE_WAT).unwrap()).unwrap();
+		let calls = HostCalls::new();
+		let (other_msgs_sink, other_msgs) = mpsc::channel();
+		let ctx = calls.mp1_ctx(move |msg| { let _ = other_msgs_sink.send(msg); });
+		let mut store = Store::new(&engine, ctx);
+		let mut linker = Linker::new(&engine);
+		mp1_wasmi::add_to_linker(&mut linker).unwrap();
+		let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+
+		let transport = ServiceTransport::new(
+			Mp1Instance::new(&mut store, instance), calls.clone()
+		);
+		let mut client = EchoClient::new(transport, JsonCodec);
+		assert_eq!(block_on(client.echo("hi".to_string())), Ok(vec!["hi".to_string()]));
+		assert_eq!(block_on(client.echo("there".to_string())), Ok(vec!["there".to_string()]));
+		drop(client);
+
+		// messages, that aren't replies to pending calls, reach handler
+		mp1_wasmi::send_into(&mut store, &instance, vec![1, 9, 0, 0, 0, 0, 0]).unwrap();
+		assert_eq!(other_msgs.try_iter().collect::<Vec<_>>(), vec![vec![2, 9, 0, 0, 0]]);
+	}
+
+}
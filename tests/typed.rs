@@ -0,0 +1,88 @@
+// Copyright(c) 2021 3NSoft Inc.
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received a copy of the GNU Lesser General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Typed messages go through every enabled codec on mocked embedding, and
+//! messages, that fail decoding, reach error handler.
+
+#![cfg(any(feature = "json", feature = "cbor", feature = "msgpack", feature = "bincode"))]
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use wasm_message_passing_3nweb::testing;
+use wasm_message_passing_3nweb::typed::{self, Codec};
+
+type Value = (u32, String, Vec<i64>, Option<bool>, BTreeMap<String, f64>);
+
+fn value() -> Value {
+	let map = [("pi".to_string(), 3.25), ("e".to_string(), -2.5)].into();
+	(7, "seven".to_string(), vec![-1, 0, i64::MAX], Some(false), map)
+}
+
+/// Sends value with codec, and gives sent message back to typed processor.
+fn assert_round_trip<C: Codec + Clone + 'static>(codec: C) {
+	typed::send(&codec, &value()).unwrap();
+	let sent = testing::take_sent_msgs();
+	assert_eq!(sent.len(), 1);
+
+	let received = Rc::new(RefCell::new(Vec::new()));
+	let processed = received.clone();
+	typed::set_typed_processor(
+		codec,
+		move |value: Value| processed.borrow_mut().push(value),
+		|failure| panic!("decoding failed: {}", failure.error),
+	);
+	testing::inject_msg(sent[0].clone());
+	assert_eq!(received.take(), [value()]);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_round_trip() {
+	assert_round_trip(typed::JsonCodec);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_round_trip() {
+	assert_round_trip(typed::CborCodec);
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_round_trip() {
+	assert_round_trip(typed::MsgPackCodec);
+}
+
+#[cfg(feature = "bincode")]
+#[test]
+fn bincode_round_trip() {
+	assert_round_trip(typed::BincodeCodec);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn malformed_msgs_reach_err_handler() {
+	let failures = Rc::new(RefCell::new(Vec::new()));
+	let failed = failures.clone();
+	typed::set_typed_processor(
+		typed::JsonCodec,
+		|value: Value| panic!("unexpected value {value:?}"),
+		move |failure| failed.borrow_mut().push(failure.msg),
+	);
+	testing::inject_msg(b"{not json".to_vec());
+	testing::inject_msg(b"[1, 2]".to_vec());
+	assert_eq!(failures.take(), [b"{not json".to_vec(), b"[1, 2]".to_vec()]);
+}